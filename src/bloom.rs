@@ -0,0 +1,77 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Bits per expected word; with `HASHES` hashes about 1% of absent words test positive.
+const BITS_PER_ITEM: usize = 10;
+const HASHES: u64 = 7;
+
+/// A set that answers "definitely absent" or "maybe present": inserted words are never
+/// reported absent, but some absent words may be reported present.
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        BloomFilter::with_capacity(0)
+    }
+}
+
+impl BloomFilter {
+    /// A filter sized for about `items` words.
+    pub fn with_capacity(items: usize) -> Self {
+        BloomFilter {
+            bits: vec![0; (items * BITS_PER_ITEM).div_ceil(64).max(1)],
+        }
+    }
+
+    pub fn insert(&mut self, word: &str) {
+        for bit in self.bit_indexes(word).collect::<Vec<_>>() {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn may_contain(&self, word: &str) -> bool {
+        self.bit_indexes(word)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The bits of `word`, derived from the two halves of one hash.
+    fn bit_indexes(&self, word: &str) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        word.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (first, step) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let len = self.bits.len() as u64 * 64;
+        (0..HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(step)) % len) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let words = (0..5000).map(|i| format!("word{i}")).collect::<Vec<_>>();
+        let mut filter = BloomFilter::with_capacity(words.len());
+        for word in &words {
+            filter.insert(word);
+        }
+        assert!(words.iter().all(|word| filter.may_contain(word)));
+        let false_positives = (0..5000)
+            .filter(|i| filter.may_contain(&format!("other{i}")))
+            .count();
+        assert!(false_positives < 250, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn test_grows_past_capacity_without_false_negatives() {
+        let mut filter = BloomFilter::default();
+        assert!(!filter.may_contain("anything"));
+        for i in 0..100 {
+            filter.insert(&i.to_string());
+        }
+        assert!((0..100).all(|i| filter.may_contain(&i.to_string())));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::index::WordIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::{fs, io, path::Path};
+use tracing::info;
+
+/// Bumped whenever the file layout changes; caches of other versions are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// Most words saved, so that the cache does not grow with every session.
+const MAX_CACHED_WORDS: usize = 10_000;
+
+/// Word frequencies saved at shutdown and loaded at the next start.
+#[derive(Debug, Serialize, Deserialize)]
+struct WordCache {
+    version: u32,
+    words: HashMap<String, usize>,
+}
+
+/// Loads the words saved by [`save_word_cache`]. A missing, corrupt or outdated cache yields
+/// an empty index, since it only ever improves the first completions of a session.
+pub fn load_word_cache(path: &Path) -> WordIndex {
+    let cache = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<WordCache>(&content).ok());
+    match cache {
+        Some(cache) if cache.version == CACHE_VERSION => WordIndex::from(cache.words),
+        Some(cache) => {
+            info!(
+                "Ignoring word cache version {} in {}",
+                cache.version,
+                path.display()
+            );
+            WordIndex::default()
+        }
+        None => WordIndex::default(),
+    }
+}
+
+/// Saves the `MAX_CACHED_WORDS` most frequent `words`; ties keep the alphabetically first.
+pub fn save_word_cache(path: &Path, words: HashMap<String, usize>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut words = words.into_iter().collect::<Vec<_>>();
+    words.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    words.truncate(MAX_CACHED_WORDS);
+    let cache = WordCache {
+        version: CACHE_VERSION,
+        words: words.into_iter().collect(),
+    };
+    fs::write(path, serde_json::to_string(&cache)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_cache_round_trip() {
+        let path = std::env::temp_dir().join("lsp-word-test-cache/words.json");
+        let _ = fs::remove_file(&path);
+        let words = HashMap::from([("alpha".to_string(), 3), ("beta".to_string(), 1)]);
+        save_word_cache(&path, words).unwrap();
+
+        let cache = load_word_cache(&path);
+        assert_eq!(cache.prefix_completions(""), ["alpha", "beta"]);
+        assert_eq!(cache.count("alpha"), 3);
+        assert_eq!(cache.count("beta"), 1);
+    }
+
+    #[test]
+    fn test_word_cache_keeps_the_most_frequent_words() {
+        let path = std::env::temp_dir().join("lsp-word-test-cache-capped/words.json");
+        let mut words = (0..MAX_CACHED_WORDS)
+            .map(|i| (format!("word{i}"), 2))
+            .collect::<HashMap<_, _>>();
+        words.insert("rare".to_string(), 1);
+        save_word_cache(&path, words).unwrap();
+
+        let cache = load_word_cache(&path);
+        assert_eq!(cache.prefix_completions("").len(), MAX_CACHED_WORDS);
+        assert!(!cache.contains("rare"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unusable_word_cache_is_empty() {
+        let dir = std::env::temp_dir().join("lsp-word-test-cache-unusable");
+        fs::create_dir_all(&dir).unwrap();
+
+        let corrupt = dir.join("corrupt.json");
+        fs::write(&corrupt, "{ not json").unwrap();
+        assert!(load_word_cache(&corrupt).prefix_completions("").is_empty());
+
+        let outdated = dir.join("outdated.json");
+        fs::write(&outdated, r#"{ "version": 0, "words": { "alpha": 1 } }"#).unwrap();
+        assert!(load_word_cache(&outdated).prefix_completions("").is_empty());
+
+        assert!(
+            load_word_cache(&dir.join("missing.json"))
+                .prefix_completions("")
+                .is_empty()
+        );
+    }
+}
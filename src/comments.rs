@@ -0,0 +1,211 @@
+use std::ops::Range;
+
+/// Comment and string delimiters of a language, enough to find its prose without parsing it.
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [u8],
+    /// `'` starts a character literal when one follows, and a lifetime or label otherwise.
+    char_literals: bool,
+}
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: b"\"'",
+    char_literals: false,
+};
+
+fn syntax(language_id: &str) -> Syntax {
+    match language_id {
+        // `'` also starts lifetimes and labels.
+        "rust" => Syntax {
+            quotes: b"\"",
+            char_literals: true,
+            ..C_LIKE
+        },
+        "javascript" | "javascriptreact" | "typescript" | "typescriptreact" => Syntax {
+            quotes: b"\"'`",
+            ..C_LIKE
+        },
+        "python" | "ruby" | "perl" | "r" | "shellscript" | "yaml" | "toml" | "makefile"
+        | "dockerfile" => Syntax {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: b"\"'",
+            char_literals: false,
+        },
+        _ => C_LIKE,
+    }
+}
+
+/// A comment or string literal found by [`spans`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    /// The whole span, delimiters included.
+    pub range: Range<usize>,
+    /// The text between the delimiters.
+    pub inner: Range<usize>,
+    pub is_block_comment: bool,
+}
+
+/// Byte ranges of the comments and string literals in `text`, without their delimiters.
+/// Languages other than the few known ones are scanned with C-like syntax.
+pub fn prose_ranges(text: &str, language_id: &str) -> Vec<Range<usize>> {
+    spans(text, language_id)
+        .into_iter()
+        .map(|span| span.inner)
+        .collect()
+}
+
+/// The comments and string literals of `text`, in order, for skipping them in code.
+pub fn spans(text: &str, language_id: &str) -> Vec<Span> {
+    let syntax = syntax(language_id);
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        // Bytes rather than `&str`, since `i` may be inside a multi-byte character.
+        let rest = &bytes[i..];
+        if let Some(start) = syntax
+            .line_comments
+            .iter()
+            .find(|start| rest.starts_with(start.as_bytes()))
+        {
+            let begin = i + start.len();
+            let end = text[begin..].find('\n').map_or(text.len(), |n| begin + n);
+            spans.push(Span {
+                range: i..end,
+                inner: begin..end,
+                is_block_comment: false,
+            });
+            i = end;
+        } else if let Some((open, close)) = syntax
+            .block_comment
+            .filter(|(open, _)| rest.starts_with(open.as_bytes()))
+        {
+            let begin = i + open.len();
+            let end = text[begin..].find(close).map_or(text.len(), |n| begin + n);
+            let after = (end + close.len()).min(text.len());
+            spans.push(Span {
+                range: i..after,
+                inner: begin..end,
+                is_block_comment: true,
+            });
+            i = after;
+        } else if syntax.char_literals
+            && bytes[i] == b'\''
+            && let Some(len) = char_literal_len(&text[i..])
+        {
+            spans.push(Span {
+                range: i..i + len,
+                inner: i + 1..i + len - 1,
+                is_block_comment: false,
+            });
+            i += len;
+        } else if syntax.quotes.contains(&bytes[i]) {
+            let quote = bytes[i];
+            let begin = i + 1;
+            let mut end = begin;
+            while end < bytes.len() && bytes[end] != quote {
+                // An escaped character never closes the string.
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            let end = end.min(bytes.len());
+            let after = (end + 1).min(bytes.len());
+            spans.push(Span {
+                range: i..after,
+                inner: begin..end,
+                is_block_comment: false,
+            });
+            i = after;
+        } else {
+            i += 1;
+        }
+    }
+    spans
+}
+
+/// Length of the character literal `rest` starts with, such as `'x'`, `'\''` or `'\u{7FFF}'`;
+/// `None` when the `'` starts a lifetime or label instead.
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let body = rest.strip_prefix('\'')?;
+    let content = match body.strip_prefix('\\') {
+        // The escaped character may itself be a `'`; the longest escape is `\u{10FFFF}`.
+        Some(escape) => {
+            let first = escape.chars().next()?;
+            let end = escape[first.len_utf8()..]
+                .char_indices()
+                .take(9)
+                .find(|&(_, c)| c == '\'')?
+                .0;
+            1 + first.len_utf8() + end
+        }
+        None => body
+            .chars()
+            .next()
+            .filter(|&c| c != '\'' && c != '\n')?
+            .len_utf8(),
+    };
+    body[content..].starts_with('\'').then_some(content + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prose<'a>(text: &'a str, language_id: &str) -> Vec<&'a str> {
+        prose_ranges(text, language_id)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_prose_ranges_c_like() {
+        let text = "let x = \"say \\\"hi\\\"\"; // trailing note\n/* block\ntext */ fn f<'a>() {}";
+        assert_eq!(
+            prose(text, "rust"),
+            ["say \\\"hi\\\"", " trailing note", " block\ntext "]
+        );
+    }
+
+    #[test]
+    fn test_prose_ranges_rust_char_literals() {
+        let text = "let q = '\"'; // note\nlet c = '\\''; /* more */\n\
+                    fn f<'a>(x: &'a str) -> char { 'é' }";
+        assert_eq!(prose(text, "rust"), ["\"", " note", "\\'", " more ", "é"]);
+        assert_eq!(char_literal_len("'\\u{1F600}' rest"), Some(11));
+        assert_eq!(char_literal_len("'\\u{10FFFF}'"), Some(12));
+        assert_eq!(char_literal_len("'a>"), None);
+        assert_eq!(char_literal_len("'outer: loop"), None);
+    }
+
+    #[test]
+    fn test_prose_ranges_hash_comments() {
+        let text = "x = 'single' # why\nprint(\"double\")";
+        assert_eq!(prose(text, "python"), ["single", " why", "double"]);
+    }
+
+    #[test]
+    fn test_prose_ranges_non_ascii_code() {
+        assert_eq!(prose("let café = \"naïve\"; // é", "rust"), ["naïve", " é"]);
+    }
+
+    #[test]
+    fn test_spans_include_delimiters() {
+        let text = "f(\"{\") /* } */";
+        let spans = spans(text, "c");
+        let ranges = spans
+            .iter()
+            .map(|span| (&text[span.range.clone()], span.is_block_comment))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [("\"{\"", false), ("/* } */", true)]);
+    }
+
+    #[test]
+    fn test_prose_ranges_unterminated() {
+        assert_eq!(prose("code /* open", "c"), [" open"]);
+        assert_eq!(prose("code \"open\\", "c"), ["open\\"]);
+    }
+}
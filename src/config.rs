@@ -0,0 +1,776 @@
+use crate::dictionary::{Dictionary, load_dictionary};
+use crate::index::WordIndex;
+use crate::tags::load_tags;
+use crate::text::Encoding;
+use lsp_types::TraceValue;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use tracing::warn;
+
+/// Settings as sent by the client, e.g. in `initializationOptions`.
+/// Every field is optional so that a partial object only overrides what it names.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Settings {
+    all_documents: Option<bool>,
+    word_pattern: Option<String>,
+    min_word_length: Option<i64>,
+    split_identifiers: Option<bool>,
+    max_completion_items: Option<usize>,
+    case_sensitive: Option<bool>,
+    rename_all_documents: Option<bool>,
+    /// `Some(None)` for an explicit `null`, which removes the dictionary.
+    #[serde(default, deserialize_with = "present")]
+    dictionary_path: Option<Option<PathBuf>>,
+    fuzzy: Option<bool>,
+    exclude_numbers: Option<bool>,
+    snippet_completions: Option<bool>,
+    trigger_characters: Option<Vec<String>>,
+    include_dots: Option<bool>,
+    exclude_keywords: Option<bool>,
+    stop_words: Option<Vec<String>>,
+    max_file_size_bytes: Option<u64>,
+    log_level: Option<String>,
+    max_cached_documents: Option<usize>,
+    /// `Some(None)` for an explicit `null`, which removes the tags.
+    #[serde(default, deserialize_with = "present")]
+    tags_path: Option<Option<PathBuf>>,
+    min_prefix_length: Option<usize>,
+    sort_mode: Option<SortMode>,
+    respect_gitignore: Option<bool>,
+    merge_casing: Option<bool>,
+    spell_check: Option<bool>,
+    commit_characters: Option<Vec<String>>,
+    comments_only: Option<Vec<String>>,
+    show_source: Option<bool>,
+    allow_leading_digits: Option<bool>,
+    language_patterns: Option<HashMap<String, String>>,
+    ranking: Option<Ranking>,
+    prose: Option<bool>,
+    lint_duplicate_words: Option<bool>,
+    log_dir: Option<PathBuf>,
+    trim_trailing_whitespace_on_save: Option<bool>,
+    completion_context_lines: Option<u32>,
+}
+
+/// Deserializes a field that is present, `null` included, as `Some`; absent fields stay `None`
+/// through `#[serde(default)]`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// What [`Config::tokenization`] compares, so that a settings change only rebuilds the word
+/// indexes when words would come out differently.
+#[derive(Debug, PartialEq)]
+pub struct Tokenization {
+    word_pattern: String,
+    language_patterns: BTreeMap<String, String>,
+    min_word_length: usize,
+    split_identifiers: bool,
+    exclude_numbers: bool,
+    include_dots: bool,
+    comments_only: Vec<String>,
+}
+
+/// Order of the completion items, as the `sortText` the client sorts by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    /// By relevance: match quality, recency, proximity and frequency.
+    #[default]
+    Frequency,
+    /// Case-insensitively by word.
+    Alphabetical,
+    /// No `sortText`; the client orders the items itself.
+    None,
+}
+
+/// Weights of the signals summed into a completion's score under `ranking`. Each signal is
+/// scaled to between 0 and 1; weights left out are 0, except `frequency`, which is 1.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Ranking {
+    pub frequency: f64,
+    pub recency: f64,
+    pub proximity: f64,
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Ranking {
+            frequency: 1.0,
+            recency: 0.0,
+            proximity: 0.0,
+        }
+    }
+}
+
+/// Most trigger or commit characters advertised; long lists make some clients throttle
+/// completion requests or reject the list. The default triggers, the lowercase ASCII letters,
+/// fit; a word typed with a capital still triggers on its next letter.
+const MAX_CHARACTERS: usize = 32;
+
+/// Most `completionContextLines`; more would turn the documentation into a page of the file.
+const MAX_CONTEXT_LINES: u32 = 10;
+
+pub const DEFAULT_WORD_PATTERN: &str = r"[\p{XID_Start}_]\p{XID_Continue}*";
+
+/// Compiled once and shared; cloning a `Regex` does not recompile it.
+static DEFAULT_WORD_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(DEFAULT_WORD_PATTERN).expect("Default word pattern is valid"));
+
+/// The default pattern under `allowLeadingDigits`.
+pub const LEADING_DIGITS_WORD_PATTERN: &str = r"\p{XID_Continue}+";
+
+static LEADING_DIGITS_WORD_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(LEADING_DIGITS_WORD_PATTERN).expect("Leading digits word pattern is valid")
+});
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Offer words from every open document instead of only the requested one.
+    pub all_documents: bool,
+    /// Pattern matching a single word.
+    pub word_pattern: Regex,
+    /// Words shorter than this many characters are dropped. Always at least 1.
+    pub min_word_length: usize,
+    /// Also offer the camelCase and snake_case components of compound identifiers.
+    pub split_identifiers: bool,
+    /// Completion responses are truncated to this many items and marked incomplete.
+    pub max_completion_items: usize,
+    /// Match the typed prefix case-sensitively.
+    pub case_sensitive: bool,
+    /// Rename a word in every open document instead of only the requested one.
+    pub rename_all_documents: bool,
+    /// Words from the file at `dictionaryPath`, offered alongside document words.
+    pub dictionary: Dictionary,
+    /// Where `dictionary` was loaded from, so that words can be added to it.
+    pub dictionary_path: Option<PathBuf>,
+    /// Match the typed characters as an in-order subsequence instead of a prefix.
+    pub fuzzy: bool,
+    /// Drop tokens that are numeric literals, such as `42` or `0xFF`.
+    pub exclude_numbers: bool,
+    /// Insert compound identifiers as snippets with a tab stop on each underscore-separated part.
+    pub snippet_completions: bool,
+    /// Characters that make the client request completion. Only read at initialization.
+    pub trigger_characters: Vec<String>,
+    /// Also offer dot-joined runs such as `foo.bar` as single words.
+    pub include_dots: bool,
+    /// Leave out the keywords of the requesting document's language.
+    pub exclude_keywords: bool,
+    /// Lowercased words never offered as completions, whatever their case.
+    pub stop_words: HashSet<String>,
+    /// Workspace files larger than this are assumed to be generated and are not indexed.
+    pub max_file_size_bytes: u64,
+    /// Minimum level of the messages written to the log. Only read at initialization.
+    pub log_level: tracing::Level,
+    /// Keep the word indexes of at most this many documents, dropping the least recently used.
+    pub max_cached_documents: Option<usize>,
+    /// Tag names from the ctags file at `tagsPath`, offered alongside document words.
+    pub tags: WordIndex,
+    /// The file `tags` were read from.
+    pub tags_path: Option<PathBuf>,
+    /// Automatically triggered completion stays empty until this many characters are typed.
+    pub min_prefix_length: usize,
+    /// How completion items are ordered for the client.
+    pub sort_mode: SortMode,
+    /// Skip workspace paths excluded by `.gitignore` and `.ignore` files or `.git/info/exclude`.
+    pub respect_gitignore: bool,
+    /// Collapse words differing only in case into their most frequent casing.
+    pub merge_casing: bool,
+    /// Properties the client accepts in `CompletionList.itemDefaults`. Taken from the client
+    /// capabilities rather than the settings.
+    pub completion_item_defaults: Vec<String>,
+    /// Whether the client honors `CompletionItem.commitCharacters`, from its capabilities.
+    pub commit_characters_support: bool,
+    /// How much to report through `$/logTrace`, set by the client rather than the settings.
+    pub trace: TraceValue,
+    /// How positions count characters, negotiated with the client rather than set by the
+    /// settings.
+    pub position_encoding: Encoding,
+    /// Publish hints for words missing from the dictionary.
+    pub spell_check: bool,
+    /// Characters that accept a completion and are then typed, if the client supports them.
+    pub commit_characters: Vec<String>,
+    /// Language ids of documents whose words are only taken from comments and string literals.
+    pub comments_only: Vec<String>,
+    /// Show the file a completion's word occurs in most as its label description.
+    pub show_source: bool,
+    /// Let words of the default pattern start with a digit, such as `2fa` or `3d`.
+    pub allow_leading_digits: bool,
+    /// Word patterns replacing `word_pattern` in documents of the given language ids.
+    pub language_patterns: HashMap<String, Regex>,
+    /// Problems with the settings, logged and kept until they are shown to the user.
+    pub problems: Vec<String>,
+    /// Words pushed by the client with `lsp-word/addWords`, kept until `lsp-word/clearWords`.
+    pub added_words: WordIndex,
+    /// The client registers `workspace/didChangeWatchedFiles` dynamically, set by its
+    /// capabilities rather than the settings.
+    pub watch_files_support: bool,
+    /// The client answers `workspace/configuration` requests, set by its capabilities rather
+    /// than the settings.
+    pub configuration_support: bool,
+    /// Weights combining frequency, recency and proximity into one score; `None` ranks by
+    /// recency, then proximity, then frequency.
+    pub ranking: Option<Ranking>,
+    /// Rank words sharing an English stem, such as `run`, `runs` and `running`, as one word.
+    pub prose: bool,
+    /// Warn about a word repeated right after itself, such as "the the".
+    pub lint_duplicate_words: bool,
+    /// Directory for the log and the word cache instead of the default one. Only read at
+    /// initialization.
+    pub log_dir: Option<PathBuf>,
+    /// Answer `textDocument/willSaveWaitUntil` with edits deleting trailing whitespace.
+    pub trim_trailing_whitespace_on_save: bool,
+    /// Lines shown before and after the line of a resolved completion's word.
+    pub completion_context_lines: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            all_documents: true,
+            word_pattern: DEFAULT_WORD_REGEX.clone(),
+            min_word_length: 2,
+            split_identifiers: false,
+            max_completion_items: 200,
+            case_sensitive: false,
+            rename_all_documents: false,
+            dictionary: Dictionary::default(),
+            dictionary_path: None,
+            fuzzy: false,
+            exclude_numbers: true,
+            snippet_completions: false,
+            trigger_characters: ('a'..='z').map(String::from).collect(),
+            include_dots: false,
+            exclude_keywords: false,
+            stop_words: HashSet::new(),
+            max_file_size_bytes: 1024 * 1024,
+            log_level: tracing::Level::INFO,
+            max_cached_documents: None,
+            tags: WordIndex::default(),
+            tags_path: None,
+            min_prefix_length: 0,
+            sort_mode: SortMode::Frequency,
+            respect_gitignore: true,
+            merge_casing: false,
+            completion_item_defaults: Vec::new(),
+            commit_characters_support: false,
+            position_encoding: Encoding::Utf16,
+            trace: TraceValue::Off,
+            spell_check: false,
+            commit_characters: Vec::new(),
+            comments_only: Vec::new(),
+            show_source: false,
+            allow_leading_digits: false,
+            language_patterns: HashMap::new(),
+            problems: Vec::new(),
+            added_words: WordIndex::default(),
+            watch_files_support: false,
+            configuration_support: false,
+            ranking: None,
+            prose: false,
+            lint_duplicate_words: false,
+            log_dir: None,
+            trim_trailing_whitespace_on_save: false,
+            completion_context_lines: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_value(value: Option<Value>) -> Self {
+        let mut config = Config::default();
+        if let Some(value) = value {
+            config.update(value);
+        }
+        config
+    }
+
+    /// Whether any diagnostics are published for open documents.
+    pub fn publishes_diagnostics(&self) -> bool {
+        self.spell_check || self.lint_duplicate_words
+    }
+
+    /// The pattern matching a word in documents of `language_id`.
+    pub fn word_pattern_for(&self, language_id: &str) -> &Regex {
+        self.language_patterns
+            .get(language_id)
+            .unwrap_or(&self.word_pattern)
+    }
+
+    /// The settings deciding which words are read from a text.
+    pub fn tokenization(&self) -> Tokenization {
+        Tokenization {
+            word_pattern: self.word_pattern.as_str().to_string(),
+            language_patterns: self
+                .language_patterns
+                .iter()
+                .map(|(language_id, pattern)| (language_id.clone(), pattern.as_str().to_string()))
+                .collect(),
+            min_word_length: self.min_word_length,
+            split_identifiers: self.split_identifiers,
+            exclude_numbers: self.exclude_numbers,
+            include_dots: self.include_dots,
+            comments_only: self.comments_only.clone(),
+        }
+    }
+
+    /// The settings deciding which workspace files are read: `respectGitignore` and
+    /// `maxFileSizeBytes`.
+    pub fn scan_settings(&self) -> (bool, u64) {
+        (self.respect_gitignore, self.max_file_size_bytes)
+    }
+
+    pub fn update(&mut self, value: Value) {
+        if value.is_null() {
+            return;
+        }
+        let settings = match serde_json::from_value::<Settings>(value.clone()) {
+            Ok(settings) => settings,
+            // Only the fields that fail are left out, so that one typo keeps the rest.
+            Err(_) if value.is_object() => self.valid_fields(value),
+            Err(e) => return self.report(format!("Invalid settings: {e}")),
+        };
+        self.apply(settings);
+    }
+
+    /// The fields of the settings object `value` that deserialize on their own, reporting
+    /// the others.
+    fn valid_fields(&mut self, value: Value) -> Settings {
+        let Value::Object(fields) = value else {
+            return Settings::default();
+        };
+        let mut valid = serde_json::Map::new();
+        for (name, field) in fields {
+            let single = serde_json::Map::from_iter([(name.clone(), field)]);
+            match serde_json::from_value::<Settings>(Value::Object(single.clone())) {
+                Ok(_) => valid.extend(single),
+                Err(e) => self.report(format!("Invalid {name}, ignoring it: {e}")),
+            }
+        }
+        serde_json::from_value(Value::Object(valid)).unwrap_or_default()
+    }
+
+    fn report(&mut self, problem: String) {
+        warn!("{problem}");
+        self.problems.push(problem);
+    }
+
+    /// `characters` without duplicates and without entries that are not a single character,
+    /// which the protocol requires, cut to `MAX_CHARACTERS`. Dropped entries are reported.
+    fn single_characters(&mut self, setting: &str, characters: Vec<String>) -> Vec<String> {
+        let mut kept = Vec::new();
+        for character in characters {
+            if character.chars().count() != 1 {
+                self.report(format!(
+                    "Ignoring {setting} entry {character:?}: not a single character"
+                ));
+            } else if !kept.contains(&character) {
+                kept.push(character);
+            }
+        }
+        if kept.len() > MAX_CHARACTERS {
+            self.report(format!(
+                "Only the first {MAX_CHARACTERS} {setting} are used"
+            ));
+            kept.truncate(MAX_CHARACTERS);
+        }
+        kept
+    }
+
+    fn apply(&mut self, settings: Settings) {
+        if let Some(all_documents) = settings.all_documents {
+            self.all_documents = all_documents;
+        }
+        if let Some(pattern) = settings.word_pattern {
+            match Regex::new(&pattern) {
+                Ok(regex) => self.word_pattern = regex,
+                Err(e) => self.report(format!("Invalid wordPattern {pattern:?}: {e}")),
+            }
+        }
+        if let Some(min_word_length) = settings.min_word_length {
+            self.min_word_length = usize::try_from(min_word_length.max(1)).unwrap_or(usize::MAX);
+        }
+        if let Some(split_identifiers) = settings.split_identifiers {
+            self.split_identifiers = split_identifiers;
+        }
+        if let Some(max_completion_items) = settings.max_completion_items {
+            self.max_completion_items = max_completion_items;
+        }
+        if let Some(case_sensitive) = settings.case_sensitive {
+            self.case_sensitive = case_sensitive;
+        }
+        if let Some(rename_all_documents) = settings.rename_all_documents {
+            self.rename_all_documents = rename_all_documents;
+        }
+        match settings.dictionary_path {
+            // Like `tagsPath`, the file is only read, and decompressed, when the path changes.
+            Some(Some(path)) if self.dictionary_path.as_ref() == Some(&path) => {}
+            Some(Some(path)) => match load_dictionary(&path) {
+                Ok(dictionary) => {
+                    self.dictionary = dictionary;
+                    self.dictionary_path = Some(path);
+                }
+                Err(e) => self.report(format!("Ignoring dictionary {}: {e}", path.display())),
+            },
+            Some(None) => {
+                self.dictionary = Dictionary::default();
+                self.dictionary_path = None;
+            }
+            None => {}
+        }
+        if let Some(fuzzy) = settings.fuzzy {
+            self.fuzzy = fuzzy;
+        }
+        if let Some(exclude_numbers) = settings.exclude_numbers {
+            self.exclude_numbers = exclude_numbers;
+        }
+        if let Some(snippet_completions) = settings.snippet_completions {
+            self.snippet_completions = snippet_completions;
+        }
+        if let Some(trigger_characters) = settings.trigger_characters {
+            self.trigger_characters =
+                self.single_characters("triggerCharacters", trigger_characters);
+        }
+        if let Some(include_dots) = settings.include_dots {
+            self.include_dots = include_dots;
+        }
+        if let Some(exclude_keywords) = settings.exclude_keywords {
+            self.exclude_keywords = exclude_keywords;
+        }
+        if let Some(stop_words) = settings.stop_words {
+            self.stop_words = stop_words.iter().map(|word| word.to_lowercase()).collect();
+        }
+        if let Some(max_file_size_bytes) = settings.max_file_size_bytes {
+            self.max_file_size_bytes = max_file_size_bytes;
+        }
+        if let Some(level) = settings.log_level {
+            self.log_level = level.parse().unwrap_or_else(|_| {
+                self.report(format!("Invalid logLevel {level:?}, using info"));
+                tracing::Level::INFO
+            });
+        }
+        if let Some(max_cached_documents) = settings.max_cached_documents {
+            self.max_cached_documents = Some(max_cached_documents);
+        }
+        match settings.tags_path {
+            // Settings updates repeat the path; the file is only read when it changes.
+            Some(Some(path)) if self.tags_path.as_ref() == Some(&path) => {}
+            Some(Some(path)) => match load_tags(&path) {
+                Ok(tags) => {
+                    self.tags = tags;
+                    self.tags_path = Some(path);
+                }
+                Err(e) => self.report(format!("Ignoring tags file {}: {e}", path.display())),
+            },
+            Some(None) => {
+                self.tags = WordIndex::default();
+                self.tags_path = None;
+            }
+            None => {}
+        }
+        if let Some(min_prefix_length) = settings.min_prefix_length {
+            self.min_prefix_length = min_prefix_length;
+        }
+        if let Some(sort_mode) = settings.sort_mode {
+            self.sort_mode = sort_mode;
+        }
+        if let Some(respect_gitignore) = settings.respect_gitignore {
+            self.respect_gitignore = respect_gitignore;
+        }
+        if let Some(merge_casing) = settings.merge_casing {
+            self.merge_casing = merge_casing;
+        }
+        if let Some(spell_check) = settings.spell_check {
+            self.spell_check = spell_check;
+        }
+        if let Some(commit_characters) = settings.commit_characters {
+            self.commit_characters = self.single_characters("commitCharacters", commit_characters);
+        }
+        if let Some(comments_only) = settings.comments_only {
+            self.comments_only = comments_only;
+        }
+        if let Some(show_source) = settings.show_source {
+            self.show_source = show_source;
+        }
+        if let Some(allow_leading_digits) = settings.allow_leading_digits {
+            self.allow_leading_digits = allow_leading_digits;
+        }
+        // An explicit `wordPattern` decides for itself what words may start with.
+        if [DEFAULT_WORD_PATTERN, LEADING_DIGITS_WORD_PATTERN].contains(&self.word_pattern.as_str())
+        {
+            let regex = if self.allow_leading_digits {
+                &LEADING_DIGITS_WORD_REGEX
+            } else {
+                &DEFAULT_WORD_REGEX
+            };
+            self.word_pattern = Regex::clone(regex);
+        }
+        if let Some(patterns) = settings.language_patterns {
+            self.language_patterns.clear();
+            for (language_id, pattern) in patterns {
+                match Regex::new(&pattern) {
+                    Ok(regex) => {
+                        self.language_patterns.insert(language_id, regex);
+                    }
+                    Err(e) => self.report(format!(
+                        "Invalid languagePatterns pattern {pattern:?} for {language_id}: {e}"
+                    )),
+                }
+            }
+        }
+        if let Some(ranking) = settings.ranking {
+            if [ranking.frequency, ranking.recency, ranking.proximity]
+                .iter()
+                .all(|weight| weight.is_finite() && *weight >= 0.0)
+            {
+                self.ranking = Some(ranking);
+            } else {
+                self.report(format!(
+                    "Invalid ranking {ranking:?}: weights must not be negative"
+                ));
+            }
+        }
+        if let Some(prose) = settings.prose {
+            self.prose = prose;
+        }
+        if let Some(lint_duplicate_words) = settings.lint_duplicate_words {
+            self.lint_duplicate_words = lint_duplicate_words;
+        }
+        if let Some(log_dir) = settings.log_dir {
+            self.log_dir = Some(log_dir);
+        }
+        if let Some(trim_trailing_whitespace_on_save) = settings.trim_trailing_whitespace_on_save {
+            self.trim_trailing_whitespace_on_save = trim_trailing_whitespace_on_save;
+        }
+        if let Some(completion_context_lines) = settings.completion_context_lines {
+            if completion_context_lines > MAX_CONTEXT_LINES {
+                self.report(format!(
+                    "Invalid completionContextLines {completion_context_lines}, using \
+                     {MAX_CONTEXT_LINES}"
+                ));
+            }
+            self.completion_context_lines = completion_context_lines.min(MAX_CONTEXT_LINES);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::from_value(None);
+        assert!(config.all_documents);
+        assert_eq!(config.word_pattern.as_str(), DEFAULT_WORD_PATTERN);
+    }
+
+    #[test]
+    fn test_config_from_value() {
+        let config = Config::from_value(Some(json!({ "allDocuments": false })));
+        assert!(!config.all_documents);
+    }
+
+    #[test]
+    fn test_invalid_settings_keep_defaults() {
+        let config = Config::from_value(Some(json!({ "allDocuments": "yes" })));
+        assert!(config.all_documents);
+        let config = Config::from_value(Some(json!(["allDocuments"])));
+        assert!(config.all_documents);
+        assert_eq!(config.problems.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_field_keeps_the_others() {
+        let config = Config::from_value(Some(json!({
+            "allDocuments": "yes",
+            "minWordLength": 4,
+            "fuzzy": true,
+        })));
+        assert!(config.all_documents);
+        assert_eq!(config.min_word_length, 4);
+        assert!(config.fuzzy);
+        assert_eq!(config.problems.len(), 1);
+        assert!(config.problems[0].starts_with("Invalid allDocuments, ignoring it: "));
+    }
+
+    #[test]
+    fn test_invalid_word_pattern_falls_back_to_default() {
+        let config = Config::from_value(Some(json!({ "wordPattern": "[a-" })));
+        assert_eq!(config.word_pattern.as_str(), DEFAULT_WORD_PATTERN);
+    }
+
+    #[test]
+    fn test_allow_leading_digits() {
+        let mut config = Config::from_value(Some(json!({ "allowLeadingDigits": true })));
+        assert_eq!(config.word_pattern.as_str(), LEADING_DIGITS_WORD_PATTERN);
+        config.update(json!({ "allowLeadingDigits": false }));
+        assert_eq!(config.word_pattern.as_str(), DEFAULT_WORD_PATTERN);
+        let config = Config::from_value(Some(json!({
+            "allowLeadingDigits": true,
+            "wordPattern": "[a-z]+",
+        })));
+        assert_eq!(config.word_pattern.as_str(), "[a-z]+");
+    }
+
+    #[test]
+    fn test_language_patterns() {
+        let config = Config::from_value(Some(json!({
+            "languagePatterns": { "css": "[a-z-]+", "broken": "[a-" },
+        })));
+        assert_eq!(config.word_pattern_for("css").as_str(), "[a-z-]+");
+        assert_eq!(
+            config.word_pattern_for("broken").as_str(),
+            DEFAULT_WORD_PATTERN
+        );
+        assert_eq!(
+            config.word_pattern_for("rust").as_str(),
+            DEFAULT_WORD_PATTERN
+        );
+    }
+
+    #[test]
+    fn test_ranking() {
+        assert_eq!(Config::default().ranking, None);
+        let config = Config::from_value(Some(json!({ "ranking": { "proximity": 0.5 } })));
+        let expected = Ranking {
+            frequency: 1.0,
+            recency: 0.0,
+            proximity: 0.5,
+        };
+        assert_eq!(config.ranking, Some(expected));
+        let mut config = Config::from_value(Some(json!({ "ranking": { "recency": -1 } })));
+        assert_eq!(config.ranking, None);
+        assert_eq!(config.problems.len(), 1);
+        config.update(json!({ "ranking": {} }));
+        assert_eq!(config.ranking, Some(Ranking::default()));
+    }
+
+    #[test]
+    fn test_characters_are_sanitized() {
+        let config = Config::from_value(Some(json!({
+            "triggerCharacters": [".", "::", ".", "", "é", ":"],
+            "commitCharacters": ["(", "(", "->"],
+        })));
+        assert_eq!(config.trigger_characters, [".", "é", ":"]);
+        assert_eq!(config.commit_characters, ["("]);
+        assert_eq!(config.problems.len(), 3);
+
+        let many = (0..100).map(|i| char::from_u32(0x4e00 + i).unwrap().to_string());
+        let config = Config::from_value(Some(
+            json!({ "triggerCharacters": many.collect::<Vec<_>>() }),
+        ));
+        assert_eq!(config.trigger_characters.len(), MAX_CHARACTERS);
+        assert_eq!(config.trigger_characters[0], "\u{4e00}");
+
+        // The defaults are within the limit, as are every ASCII letter sent explicitly.
+        let config = Config::default();
+        assert_eq!(config.trigger_characters.len(), 26);
+        assert!(config.trigger_characters.len() <= MAX_CHARACTERS);
+        let letters = ('A'..='Z').chain('a'..='z').map(String::from);
+        let config = Config::from_value(Some(
+            json!({ "triggerCharacters": letters.collect::<Vec<_>>() }),
+        ));
+        assert_eq!(config.trigger_characters.len(), MAX_CHARACTERS);
+        assert_eq!(config.problems.len(), 1);
+    }
+
+    #[test]
+    fn test_completion_context_lines_are_capped() {
+        let config =
+            Config::from_value(Some(json!({ "completionContextLines": 4_000_000_000u32 })));
+        assert_eq!(config.completion_context_lines, MAX_CONTEXT_LINES);
+        assert_eq!(config.problems.len(), 1);
+    }
+
+    #[test]
+    fn test_tags_are_read_when_the_path_changes() {
+        let path = std::env::temp_dir().join("lsp-word-test-config-tags");
+        fs::write(&path, "parse_header\tsrc/http.rs\t1\n").unwrap();
+        let mut config = Config::from_value(Some(json!({ "tagsPath": path })));
+        assert!(config.tags.contains("parse_header"));
+
+        // The same path again keeps the tags without reading the file.
+        fs::remove_file(&path).unwrap();
+        config.update(json!({ "tagsPath": path, "fuzzy": true }));
+        assert!(config.tags.contains("parse_header"));
+        assert!(config.problems.is_empty());
+
+        config.update(json!({ "fuzzy": false }));
+        assert!(config.tags.contains("parse_header"));
+        config.update(json!({ "tagsPath": null }));
+        assert!(config.tags.is_empty());
+        assert_eq!(config.tags_path, None);
+    }
+
+    #[test]
+    fn test_dictionary_is_read_when_the_path_changes() {
+        let path = std::env::temp_dir().join("lsp-word-test-config-dictionary");
+        fs::write(&path, "receive\n").unwrap();
+        let mut config = Config::from_value(Some(json!({ "dictionaryPath": path })));
+        assert!(config.dictionary.contains("receive"));
+
+        // The same path again keeps the words without reading the file.
+        fs::remove_file(&path).unwrap();
+        config.update(json!({ "dictionaryPath": path, "fuzzy": true }));
+        assert!(config.dictionary.contains("receive"));
+        assert!(config.problems.is_empty());
+
+        config.update(json!({ "dictionaryPath": null }));
+        assert!(!config.dictionary.contains("receive"));
+        assert_eq!(config.dictionary_path, None);
+    }
+
+    #[test]
+    fn test_min_word_length_is_clamped() {
+        let config = Config::from_value(Some(json!({ "minWordLength": -5 })));
+        assert_eq!(config.min_word_length, 1);
+    }
+
+    #[test]
+    fn test_shared_default_pattern_matches_fresh_compile() {
+        let content = "let café = größe_1; x.y_z";
+        let fresh = Regex::new(DEFAULT_WORD_PATTERN).unwrap();
+        let shared = Config::default().word_pattern;
+        assert_eq!(
+            fresh
+                .find_iter(content)
+                .map(|m| m.as_str())
+                .collect::<Vec<_>>(),
+            shared
+                .find_iter(content)
+                .map(|m| m.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(Config::default().log_level, tracing::Level::INFO);
+        let config = Config::from_value(Some(json!({ "logLevel": "debug" })));
+        assert_eq!(config.log_level, tracing::Level::DEBUG);
+        let config = Config::from_value(Some(json!({ "logLevel": "loud" })));
+        assert_eq!(config.log_level, tracing::Level::INFO);
+    }
+
+    #[test]
+    fn test_sort_mode() {
+        assert_eq!(Config::default().sort_mode, SortMode::Frequency);
+        let config = Config::from_value(Some(json!({ "sortMode": "alphabetical" })));
+        assert_eq!(config.sort_mode, SortMode::Alphabetical);
+        let config = Config::from_value(Some(json!({ "sortMode": "random" })));
+        assert_eq!(config.sort_mode, SortMode::Frequency);
+    }
+}
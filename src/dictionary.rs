@@ -0,0 +1,140 @@
+use crate::bloom::BloomFilter;
+use crate::index::WordIndex;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::{fs, io, path::Path};
+
+/// Words of a word list, with a Bloom filter so that most misspellings are rejected without
+/// searching the words.
+#[derive(Clone, Debug, Default)]
+pub struct Dictionary {
+    pub words: WordIndex,
+    filter: BloomFilter,
+}
+
+impl From<WordIndex> for Dictionary {
+    fn from(words: WordIndex) -> Self {
+        let mut filter = BloomFilter::with_capacity(words.len());
+        for (word, _) in words.iter() {
+            filter.insert(word);
+        }
+        Dictionary { words, filter }
+    }
+}
+
+impl Dictionary {
+    pub fn contains(&self, word: &str) -> bool {
+        self.filter.may_contain(word) && self.words.contains(word)
+    }
+
+    pub fn add(&mut self, word: &str) {
+        self.filter.insert(word);
+        self.words.add(word, 0);
+    }
+}
+
+/// The magic bytes that every gzip stream starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Loads a newline-separated word list, which may be gzip-compressed. Surrounding whitespace
+/// and blank lines are ignored. Dictionary words carry no occurrences of their own, so they
+/// rank after document words.
+pub fn load_dictionary(path: &Path) -> io::Result<Dictionary> {
+    let mut content = fs::read(path)?;
+    if content.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(content.as_slice()).read_to_end(&mut decompressed)?;
+        content = decompressed;
+    }
+    let content =
+        String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Dictionary::from(WordIndex::from(
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(|word| (word.to_owned(), 0))
+            .collect::<HashMap<_, _>>(),
+    )))
+}
+
+/// Appends `word` as a line of its own to the word list at `path`, creating the file if needed.
+pub fn append_word(path: &Path, word: &str) -> io::Result<()> {
+    let content = fs::read(path).unwrap_or_default();
+    if content.starts_with(&GZIP_MAGIC) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot add words to a compressed dictionary",
+        ));
+    }
+    let needs_newline = !content.is_empty() && !content.ends_with(b"\n");
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if needs_newline {
+        writeln!(file)?;
+    }
+    writeln!(file, "{word}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_dictionary() {
+        let path = std::env::temp_dir().join("lsp-word-test-load-dictionary.txt");
+        fs::write(&path, "apple\n  banana \n\napple\n").unwrap();
+        let words = load_dictionary(&path).unwrap();
+        assert_eq!(words.words.prefix_completions(""), ["apple", "banana"]);
+    }
+
+    #[test]
+    fn test_load_gzipped_dictionary() {
+        // "apple\nbanana\n", gzip-compressed.
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0x2c, 0x28, 0xc8,
+            0x49, 0xe5, 0x4a, 0x4a, 0xcc, 0x03, 0x42, 0x2e, 0x00, 0x28, 0x27, 0xf5, 0xee, 0x0d,
+            0x00, 0x00, 0x00,
+        ];
+        let path = std::env::temp_dir().join("lsp-word-test-load-dictionary.txt.gz");
+        fs::write(&path, compressed).unwrap();
+        let words = load_dictionary(&path).unwrap();
+        assert_eq!(words.words.prefix_completions(""), ["apple", "banana"]);
+        assert!(append_word(&path, "cherry").is_err());
+        assert_eq!(fs::read(&path).unwrap(), compressed);
+    }
+
+    #[test]
+    fn test_dictionary_contains() {
+        let mut dictionary = Dictionary::from(WordIndex::from(HashMap::from([
+            ("apple".to_string(), 0),
+            ("banana".to_string(), 0),
+        ])));
+        assert!(dictionary.contains("apple"));
+        assert!(!dictionary.contains("cherry"));
+        assert!(!dictionary.contains("app"));
+        dictionary.add("cherry");
+        assert!(dictionary.contains("cherry"));
+    }
+
+    #[test]
+    fn test_append_word() {
+        let path = std::env::temp_dir().join("lsp-word-test-append-dictionary.txt");
+        fs::write(&path, "apple").unwrap();
+        append_word(&path, "banana").unwrap();
+        append_word(&path, "cherry").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "apple\nbanana\ncherry\n"
+        );
+    }
+
+    #[test]
+    fn test_load_missing_dictionary() {
+        let path = std::env::temp_dir().join("lsp-word-test-missing-dictionary.txt");
+        assert!(load_dictionary(&path).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files in every directory whose patterns exclude paths from the workspace scan.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// Patterns from the ignore files of a directory and its ancestors, following the
+/// `.gitignore` format: the last matching pattern decides, and `!` re-includes.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    /// Directory of the ignore file the pattern came from.
+    base: PathBuf,
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    /// Patterns containing a slash match against the path below `base`, others against
+    /// the file name alone.
+    anchored: bool,
+}
+
+impl IgnoreRules {
+    /// The rules that reach into `root` from outside it, as git sees them: the repository's
+    /// `.git/info/exclude` and the ignore files of the directories between the repository
+    /// top and `root`, not including `root` itself. Empty outside a git repository.
+    pub fn above(root: &Path) -> IgnoreRules {
+        let mut rules = IgnoreRules::default();
+        let Some(top) = root.ancestors().find(|dir| dir.join(".git").exists()) else {
+            return rules;
+        };
+        if let Ok(content) = fs::read_to_string(top.join(".git/info/exclude")) {
+            rules.extend(top, &content);
+        }
+        let parents: Vec<&Path> = root
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(top))
+            .collect();
+        for dir in parents.into_iter().rev() {
+            rules = rules.with_dir(dir);
+        }
+        rules
+    }
+
+    /// These rules extended by the ignore files found directly in `dir`.
+    pub fn with_dir(&self, dir: &Path) -> IgnoreRules {
+        let mut rules = self.clone();
+        for name in IGNORE_FILES {
+            if let Ok(content) = fs::read_to_string(dir.join(name)) {
+                rules.extend(dir, &content);
+            }
+        }
+        rules
+    }
+
+    fn extend(&mut self, base: &Path, content: &str) {
+        self.rules
+            .extend(content.lines().filter_map(|line| Rule::parse(base, line)));
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(base: &Path, line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let line = line.strip_prefix('\\').unwrap_or(line);
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Rule {
+            base: base.to_path_buf(),
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let text: Vec<char> = if self.anchored {
+            relative
+                .to_string_lossy()
+                .replace('\\', "/")
+                .chars()
+                .collect()
+        } else {
+            match relative.file_name() {
+                Some(name) => name.to_string_lossy().chars().collect(),
+                None => return false,
+            }
+        };
+        glob_match(&self.glob, &text)
+    }
+}
+
+/// Matches `text` against a gitignore glob: `*` and `?` stay within one path segment,
+/// `**` spans segments and `[...]` is a character class.
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    match glob {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            glob_match(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &c)| c == '/' && glob_match(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(rest, &text[i..])),
+        ['?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != '/' && glob_match(rest, tail)),
+        ['[', class @ ..] => match (class_match(class, text.first()), text) {
+            (Some((true, rest)), [_, tail @ ..]) => glob_match(rest, tail),
+            (Some(_), _) => false,
+            // An unclosed bracket is an ordinary character.
+            (None, [c, tail @ ..]) => *c == '[' && glob_match(class, tail),
+            (None, []) => false,
+        },
+        ['\\', c, rest @ ..] | [c, rest @ ..] => {
+            matches!(text, [t, tail @ ..] if t == c && glob_match(rest, tail))
+        }
+    }
+}
+
+/// Whether `c` is in the class that `class` starts with, and the glob after the class;
+/// `None` when the class is not closed.
+fn class_match<'a>(class: &'a [char], c: Option<&char>) -> Option<(bool, &'a [char])> {
+    let (negated, class) = match class {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    // A `]` right after the opening bracket is part of the class.
+    let start = usize::from(class.first() == Some(&']'));
+    let end = start + class.get(start..)?.iter().position(|&c| c == ']')?;
+    let (members, rest) = (&class[..end], &class[end + 1..]);
+    let Some(&c) = c else {
+        return Some((false, rest));
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < members.len() {
+        if i + 2 < members.len() && members[i + 1] == '-' {
+            found |= (members[i]..=members[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= members[i] == c;
+            i += 1;
+        }
+    }
+    Some((found != negated && c != '/', rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        glob_match(
+            &pattern.chars().collect::<Vec<_>>(),
+            &text.chars().collect::<Vec<_>>(),
+        )
+    }
+
+    fn rules(base: &str, lines: &str) -> IgnoreRules {
+        IgnoreRules {
+            rules: lines
+                .lines()
+                .filter_map(|line| Rule::parse(Path::new(base), line))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob("*.rs", "main.rs"));
+        assert!(!glob("*.rs", "src/main.rs"));
+        assert!(glob("src/*.rs", "src/main.rs"));
+        assert!(glob("**/gen", "a/b/gen"));
+        assert!(glob("**/gen", "gen"));
+        assert!(glob("out/**", "out/a/b"));
+        assert!(glob("a/**/b", "a/b"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(glob("file?.txt", "file1.txt"));
+        assert!(!glob("file?.txt", "file10.txt"));
+        assert!(glob("[abc].txt", "b.txt"));
+        assert!(glob("[a-c].txt", "c.txt"));
+        assert!(!glob("[!a-c].txt", "c.txt"));
+        assert!(glob("[]x].txt", "].txt"));
+        assert!(glob("\\*.txt", "*.txt"));
+        assert!(!glob("\\*.txt", "a.txt"));
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = rules(
+            "/repo",
+            "# build output\ntarget/\n*.log\n!keep.log\n/root-only.txt\ndocs/*.html\n",
+        );
+        assert!(rules.is_ignored(Path::new("/repo/target"), true));
+        assert!(rules.is_ignored(Path::new("/repo/sub/target"), true));
+        assert!(!rules.is_ignored(Path::new("/repo/target"), false));
+        assert!(rules.is_ignored(Path::new("/repo/sub/debug.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/keep.log"), false));
+        assert!(rules.is_ignored(Path::new("/repo/root-only.txt"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/sub/root-only.txt"), false));
+        assert!(rules.is_ignored(Path::new("/repo/docs/index.html"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/docs/api/index.html"), false));
+        assert!(!rules.is_ignored(Path::new("/elsewhere/debug.log"), false));
+    }
+}
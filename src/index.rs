@@ -0,0 +1,203 @@
+use crate::intern::intern;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Word counts of a document, kept ordered so that the words sharing a prefix
+/// are found with a single range query instead of a scan over every word.
+/// Words are interned, so a word common to many indexes is stored once.
+#[derive(Clone, Debug, Default)]
+pub struct WordIndex {
+    counts: BTreeMap<Arc<str>, usize>,
+    /// `(lowercased, original)` pairs for case-insensitive prefix queries.
+    folded: BTreeSet<(Arc<str>, Arc<str>)>,
+}
+
+/// The `folded` entry of `word`.
+fn folded(word: &Arc<str>) -> (Arc<str>, Arc<str>) {
+    (intern(&word.to_lowercase()), Arc::clone(word))
+}
+
+impl From<HashMap<String, usize>> for WordIndex {
+    fn from(counts: HashMap<String, usize>) -> Self {
+        let counts = counts
+            .into_iter()
+            .map(|(word, count)| (intern(&word), count))
+            .collect::<BTreeMap<_, _>>();
+        let folded = counts.keys().map(folded).collect();
+        WordIndex { counts, folded }
+    }
+}
+
+impl WordIndex {
+    /// Adds `count` occurrences of `word`, e.g. while merging files as they are read.
+    pub fn add(&mut self, word: &str, count: usize) {
+        match self.counts.get_mut(word) {
+            Some(total) => *total += count,
+            None => {
+                let word = intern(word);
+                self.folded.insert(folded(&word));
+                self.counts.insert(word, count);
+            }
+        }
+    }
+
+    /// Takes back `count` occurrences of `word` added before, dropping the word at zero.
+    pub fn remove(&mut self, word: &str, count: usize) {
+        let Some(total) = self.counts.get_mut(word) else {
+            return;
+        };
+        *total = total.saturating_sub(count);
+        if *total == 0
+            && let Some((word, _)) = self.counts.remove_entry(word)
+        {
+            self.folded.remove(&folded(&word));
+        }
+    }
+
+    /// Every word with its count, in lexicographic order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.counts.iter().map(|(word, count)| (&**word, *count))
+    }
+
+    /// Whether `word` is in the index, including words added with no occurrences.
+    pub fn contains(&self, word: &str) -> bool {
+        self.counts.contains_key(word)
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Rough number of bytes held: the map entries, plus each word and its lowercased form,
+    /// though other indexes holding the same words share them.
+    pub fn memory_estimate(&self) -> usize {
+        let entry = 3 * std::mem::size_of::<Arc<str>>() + std::mem::size_of::<usize>();
+        self.counts.keys().map(|word| entry + 2 * word.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn count(&self, word: &str) -> usize {
+        self.counts.get(word).copied().unwrap_or_default()
+    }
+
+    /// Words starting with `prefix` in lexicographic order. An empty prefix yields every word.
+    pub fn prefix_completions(&self, prefix: &str) -> Vec<String> {
+        self.counts
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .map(|(word, _)| word.to_string())
+            .collect()
+    }
+
+    /// Like [`WordIndex::prefix_completions`], but ignoring case. Every casing of a
+    /// matching word is returned as it appears in the document.
+    pub fn prefix_completions_ignore_case(&self, prefix: &str) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        self.folded
+            .range((Arc::from(prefix.as_str()), Arc::from(""))..)
+            .take_while(|(folded, _)| folded.starts_with(&prefix))
+            .map(|(_, word)| word.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(words: &[&str]) -> WordIndex {
+        WordIndex::from(
+            words
+                .iter()
+                .map(|word| (word.to_string(), 1))
+                .collect::<HashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = index(&["Alpha", "beta"]);
+        index.add("Alpha", 2);
+        index.remove("Alpha", 1);
+        assert_eq!(index.count("Alpha"), 2);
+        index.remove("Alpha", 2);
+        index.remove("missing", 1);
+        assert_eq!(index.prefix_completions(""), ["beta"]);
+        assert!(index.prefix_completions_ignore_case("a").is_empty());
+    }
+
+    #[test]
+    fn test_contains_words_without_occurrences() {
+        let mut index = WordIndex::default();
+        assert!(index.is_empty());
+        index.add("listed", 0);
+        assert!(index.contains("listed"));
+        assert!(!index.contains("list"));
+        assert!(!index.is_empty());
+    }
+
+    #[test]
+    fn test_prefix_completions_hits() {
+        let index = index(&["tea", "test", "testing", "toast", "apple"]);
+        assert_eq!(index.prefix_completions("tes"), ["test", "testing"]);
+        assert_eq!(index.prefix_completions("test"), ["test", "testing"]);
+    }
+
+    #[test]
+    fn test_prefix_completions_miss() {
+        let index = index(&["tea", "test"]);
+        assert!(index.prefix_completions("z").is_empty());
+        assert!(index.prefix_completions("tests").is_empty());
+    }
+
+    #[test]
+    fn test_prefix_completions_empty_prefix() {
+        let index = index(&["b", "a", "c"]);
+        assert_eq!(index.prefix_completions(""), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_prefix_completions_ignore_case() {
+        let index = index(&["Test", "test", "TEST", "tea", "other"]);
+        assert_eq!(
+            index.prefix_completions_ignore_case("tes"),
+            ["TEST", "Test", "test"]
+        );
+        assert_eq!(
+            index.prefix_completions_ignore_case("TE"),
+            ["tea", "TEST", "Test", "test"]
+        );
+    }
+
+    #[test]
+    fn test_indexes_share_words() {
+        let first = index(&["shared", "first"]);
+        let mut second = index(&["shared"]);
+        second.add("later", 1);
+        let key =
+            |index: &WordIndex, word: &str| Arc::clone(index.counts.get_key_value(word).unwrap().0);
+        assert!(Arc::ptr_eq(&key(&first, "shared"), &key(&second, "shared")));
+        assert!(Arc::ptr_eq(&key(&second, "later"), &intern("later")));
+        // Lowercase words are their own folded form.
+        assert!(
+            first
+                .folded
+                .iter()
+                .all(|(folded, word)| Arc::ptr_eq(folded, word))
+        );
+    }
+
+    #[test]
+    fn test_add_merges_counts() {
+        let mut index = index(&["alpha"]);
+        index.add("alpha", 2);
+        index.add("Beta", 1);
+        assert_eq!(index.count("alpha"), 3);
+        assert_eq!(index.count("Beta"), 1);
+        assert_eq!(index.prefix_completions_ignore_case("b"), ["Beta"]);
+    }
+}
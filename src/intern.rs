@@ -0,0 +1,95 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// A shard is not swept before it holds this many words.
+const MIN_SWEEP_SIZE: usize = 1024;
+
+/// Independently locked parts of the interner, so that threads tokenizing different files
+/// rarely wait for each other.
+const SHARDS: usize = 16;
+
+/// Every word held by a [`WordIndex`](crate::index::WordIndex) or a workspace file, so that
+/// the indexes of different documents and files share one allocation per distinct word.
+/// Each word lives in the shard its hash picks.
+static WORDS: LazyLock<[Mutex<Interner>; SHARDS]> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+struct Interner {
+    words: HashSet<Arc<str>>,
+    /// Size after the last sweep; the next one runs once the set has doubled.
+    swept: usize,
+}
+
+impl Interner {
+    /// Forgets the words that no index holds anymore, such as those typed half-way.
+    fn sweep(&mut self) {
+        self.words.retain(|word| Arc::strong_count(word) > 1);
+        self.swept = self.words.len();
+    }
+}
+
+fn shard(word: &str) -> &'static Mutex<Interner> {
+    let mut hasher = DefaultHasher::new();
+    word.hash(&mut hasher);
+    &WORDS[hasher.finish() as usize % SHARDS]
+}
+
+/// The shared copy of `word`, allocated the first time it is seen.
+pub fn intern(word: &str) -> Arc<str> {
+    let mut interner = shard(word).lock().unwrap();
+    if let Some(word) = interner.words.get(word) {
+        return Arc::clone(word);
+    }
+    if interner.words.len() >= 2 * interner.swept.max(MIN_SWEEP_SIZE) {
+        interner.sweep();
+    }
+    let word = Arc::<str>::from(word);
+    interner.words.insert(Arc::clone(&word));
+    word
+}
+
+/// Forgets the words that nothing holds anymore in every shard, e.g. after many files left
+/// the workspace at once, rather than waiting for the shards to grow.
+pub fn sweep() {
+    for shard in WORDS.iter() {
+        shard.lock().unwrap().sweep();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_allocation() {
+        let first = intern("lsp-word-test-shared");
+        let second = intern(&String::from("lsp-word-test-shared"));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &intern("lsp-word-test-other")));
+    }
+
+    #[test]
+    fn test_intern_across_threads() {
+        let words = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| scope.spawn(|| intern("lsp-word-test-threads")))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert!(words.windows(2).all(|pair| Arc::ptr_eq(&pair[0], &pair[1])));
+    }
+
+    #[test]
+    fn test_sweep_forgets_unused_words() {
+        let word = intern("lsp-word-test-swept");
+        let unused = Arc::downgrade(&word);
+        drop(word);
+        sweep();
+        assert!(unused.upgrade().is_none());
+    }
+}
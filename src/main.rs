@@ -1,224 +1,7017 @@
-use anyhow::Result;
-use directories;
-use lsp_server::{Connection, Message, Request, Response};
+#![allow(clippy::mutable_key_type)]
+
+mod bloom;
+mod cache;
+mod comments;
+mod config;
+mod dictionary;
+mod gitignore;
+mod index;
+mod intern;
+mod keywords;
+mod progress;
+mod stem;
+mod tags;
+mod text;
+mod tokenize;
+mod workspace;
+
+use anyhow::{Result, bail};
+use config::{Config, SortMode};
+use index::WordIndex;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
 use lsp_types::notification::{self, Notification as TypesNotification};
 use lsp_types::request::{self, Request as TypesRequest};
 use lsp_types::{
-    CompletionItem, CompletionOptions, CompletionParams, CompletionResponse, ServerCapabilities,
-    TextDocumentSyncKind, Uri,
+    CancelParams, CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeActionResponse, Command, CompletionItem, CompletionItemKind,
+    CompletionItemLabelDetails, CompletionList, CompletionOptions, CompletionParams,
+    CompletionResponse, CompletionTextEdit, CompletionTriggerKind, Diagnostic, DiagnosticSeverity,
+    DidChangeWatchedFilesRegistrationOptions, DocumentHighlight, DocumentHighlightKind,
+    DocumentHighlightParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    Documentation, ExecuteCommandOptions, ExecuteCommandParams, FileSystemWatcher, FoldingRange,
+    FoldingRangeKind, FoldingRangeParams, FoldingRangeProviderCapability, GlobPattern, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, InitializeParams, InitializeResult,
+    InsertTextFormat, Location, LogTraceParams, MarkupContent, MarkupKind, MessageType,
+    NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams,
+    Registration, RegistrationParams, RenameParams, SaveOptions, SelectionRange,
+    SelectionRangeParams, SelectionRangeProviderCapability, ServerCapabilities, ServerInfo,
+    SetTraceParams, ShowMessageParams, SymbolInformation, SymbolKind,
+    TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions, TextEdit,
+    TraceValue, Uri, WillSaveTextDocumentParams, WorkspaceEdit, WorkspaceFoldersServerCapabilities,
+    WorkspaceServerCapabilities, WorkspaceSymbolParams, WorkspaceSymbolResponse,
 };
+use progress::Progress;
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
-use std::{fs, path::Path};
-use tracing::{error, info};
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
-
-fn create_log_file(base_path: &Path) -> anyhow::Result<fs::File> {
-    let dir_path = base_path.join("lsp-word");
-    fs::create_dir_all(&dir_path)?;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use text::{Encoding, LineIndex, position_to_offset};
+use tracing::Level;
+use tracing::{error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::Layered;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, reload};
+use workspace::WorkspaceIndex;
+
+/// Log files grow to this size before they are rotated, unless `LSP_WORD_LOG_MAX_SIZE` says otherwise.
+const DEFAULT_MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+/// Rotated logs kept next to the current one, as `lsp-word.log.1` (newest) and so on.
+const LOG_GENERATIONS: usize = 3;
+
+fn max_log_size() -> u64 {
+    std::env::var("LSP_WORD_LOG_MAX_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_MAX_LOG_SIZE)
+}
+
+/// Moves `file_path` to `<file_path>.1`, shifting older generations up, once it exceeds `max_size`.
+fn rotate_log(file_path: &Path, max_size: u64) -> std::io::Result<()> {
+    if fs::metadata(file_path).map_or(true, |metadata| metadata.len() <= max_size) {
+        return Ok(());
+    }
+    let backup = |generation: usize| file_path.with_extension(format!("log.{generation}"));
+    for generation in (1..LOG_GENERATIONS).rev() {
+        if backup(generation).exists() {
+            fs::rename(backup(generation), backup(generation + 1))?;
+        }
+    }
+    fs::rename(file_path, backup(1))
+}
+
+/// Directories for the log and the word cache, most preferred first: `dir` from the `logDir`
+/// setting, `LSP_WORD_DIR`, then `lsp-word` in the user's cache directory.
+fn data_dirs(dir: Option<&Path>) -> Vec<PathBuf> {
+    dir.map(Path::to_path_buf)
+        .into_iter()
+        .chain(std::env::var_os("LSP_WORD_DIR").map(PathBuf::from))
+        .chain(directories::BaseDirs::new().map(|base| base.cache_dir().join("lsp-word")))
+        .collect()
+}
+
+/// The log file in the first of `dirs` that it can be created in, with that directory.
+fn open_log_file(dirs: &[PathBuf], max_size: u64) -> Option<(PathBuf, fs::File)> {
+    dirs.iter()
+        .find_map(|dir| Some((dir.clone(), create_log_file(dir, max_size).ok()?)))
+}
+
+fn create_log_file(dir_path: &Path, max_size: u64) -> anyhow::Result<fs::File> {
+    fs::create_dir_all(dir_path)?;
     let file_path = dir_path.join("lsp-word.log");
+    rotate_log(&file_path, max_size)?;
     Ok(fs::OpenOptions::new()
         .create(true)
         .append(true)
         .open(file_path)?)
 }
 
-fn init_logger() {
-    let builder = FmtSubscriber::builder()
-        .with_env_filter(EnvFilter::from_env("LSP_WORD_LOG"))
-        .with_max_level(tracing::Level::INFO);
-    let base_dirs = directories::BaseDirs::new();
-    match base_dirs.and_then(|base| create_log_file(base.cache_dir()).ok()) {
-        Some(log_file) => builder.with_writer(Mutex::new(log_file)).init(),
-        _ => builder.with_writer(std::io::stderr).without_time().init(),
+/// Logs at `level` and above, plus whatever the `LSP_WORD_LOG` directives enable.
+fn log_filter(level: Level) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(level).into())
+        .with_env_var("LSP_WORD_LOG")
+        .from_env_lossy()
+}
+
+/// The registry behind the reloadable filter, which the output layer writes for.
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Handles swapping the log filter once the `logLevel` setting is known, and the output once
+/// `logDir` is.
+struct LoggerHandles {
+    filter: reload::Handle<EnvFilter, Registry>,
+    output: reload::Handle<Box<dyn Layer<FilteredRegistry> + Send + Sync>, FilteredRegistry>,
+    /// Directory of the log file being written, `None` while logging to stderr.
+    dir: Option<PathBuf>,
+}
+
+/// Writes to `log_file`, or to stderr without timestamps, which clients add themselves.
+fn log_output(log_file: Option<fs::File>) -> Box<dyn Layer<FilteredRegistry> + Send + Sync> {
+    match log_file {
+        Some(log_file) => fmt::layer().with_writer(Mutex::new(log_file)).boxed(),
+        None => fmt::layer()
+            .with_writer(std::io::stderr)
+            .without_time()
+            .boxed(),
     }
 }
 
-fn load_all_words(uri: Uri, docs: &HashMap<Uri, String>) -> Result<HashSet<String>> {
-    let content = docs.get(&uri).expect("Document not found");
-    Ok(Regex::new(r"[A-Za-z_][A-Za-z0-9_]+")?
-        .find_iter(&content)
-        .map(|m| m.as_str().to_owned())
-        .collect::<HashSet<String>>())
+/// Starts logging at INFO to the first writable directory of `data_dirs(None)`, or to stderr.
+fn init_logger() -> LoggerHandles {
+    let (filter, filter_handle) = reload::Layer::new(log_filter(Level::INFO));
+    let (dir, log_file) = open_log_file(&data_dirs(None), max_log_size()).unzip();
+    let (output, output_handle) = reload::Layer::new(log_output(log_file));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .init();
+    LoggerHandles {
+        filter: filter_handle,
+        output: output_handle,
+        dir,
+    }
 }
 
-fn create_completion_response(req: Request, docs: &HashMap<Uri, String>) -> Result<Message> {
-    let params = serde_json::from_value::<CompletionParams>(req.params)?;
-    let words = load_all_words(params.text_document_position.text_document.uri, docs)?;
-    let compres = CompletionResponse::Array(
-        words
-            .iter()
-            .map(|word| CompletionItem {
-                label: word.to_owned(),
-                ..Default::default()
-            })
-            .collect(),
-    );
-    Ok(Message::Response(Response::new_ok(req.id, compres)))
+/// Splits a compound identifier into its snake_case and camelCase components,
+/// keeping acronym runs together (`HTTPServer` yields `HTTP` and `Server`).
+/// Returns nothing when the identifier has a single component.
+fn split_identifier(word: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for segment in word.split('_').filter(|segment| !segment.is_empty()) {
+        let chars = segment.char_indices().collect::<Vec<_>>();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (offset, c) = chars[i];
+            let prev = chars[i - 1].1;
+            let next_is_lower = chars
+                .get(i + 1)
+                .is_some_and(|(_, next)| next.is_lowercase());
+            if c.is_uppercase() && (!prev.is_uppercase() || next_is_lower) {
+                parts.push(&segment[start..offset]);
+                start = offset;
+            }
+        }
+        parts.push(&segment[start..]);
+    }
+    if parts.len() > 1 { parts } else { Vec::new() }
 }
 
-fn serve(connection: Connection) -> Result<()> {
-    let mut docs = HashMap::new();
-    for msg in &connection.receiver {
-        match msg {
-            Message::Request(req) => match req.method.as_str() {
-                request::Shutdown::METHOD => {
-                    connection.handle_shutdown(&req)?;
-                }
-                request::Completion::METHOD => connection
-                    .sender
-                    .send(create_completion_response(req, &docs)?)?,
-                _ => (),
-            },
-            Message::Notification(not) => match not.method.as_str() {
-                notification::Exit::METHOD => (),
-                notification::DidChangeTextDocument::METHOD => {
-                    let params = serde_json::from_value::<lsp_types::DidChangeTextDocumentParams>(
-                        not.params,
-                    )?;
+/// Decimal digits only, or a `0x`-prefixed hexadecimal literal.
+fn is_number(word: &str) -> bool {
+    let (digits, radix) = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (word, 10),
+    };
+    !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix))
+}
 
-                    docs.insert(
-                        params.text_document.uri.to_owned(),
-                        params.content_changes[0].text.clone(),
-                    );
-                }
-                notification::DidOpenTextDocument::METHOD => {
-                    let params =
-                        serde_json::from_value::<lsp_types::DidOpenTextDocumentParams>(not.params)?;
-                    docs.insert(
-                        params.text_document.uri.to_owned(),
-                        params.text_document.text,
-                    );
+/// Runs of two or more words joined by single dots, such as `foo.bar.baz`.
+fn dotted_names<'a>(content: &'a str, words: &[std::ops::Range<usize>]) -> Vec<&'a str> {
+    let mut names = Vec::new();
+    let mut run: Option<(usize, usize, usize)> = None;
+    for m in words {
+        run = match run {
+            Some((start, end, len)) if m.start == end + 1 && content.as_bytes()[end] == b'.' => {
+                Some((start, m.end, len + 1))
+            }
+            _ => {
+                if let Some((start, end, 2..)) = run {
+                    names.push(&content[start..end]);
                 }
-                _ => (),
-            },
-            _ => (),
+                Some((m.start, m.end, 1))
+            }
+        };
+    }
+    if let Some((start, end, 2..)) = run {
+        names.push(&content[start..end]);
+    }
+    names
+}
+
+fn load_all_words(content: &str, config: &Config) -> HashMap<String, usize> {
+    load_words(content, "", config)
+}
+
+/// Words of `content` in the language `language_id`, which may have a pattern of its own.
+fn load_words(content: &str, language_id: &str, config: &Config) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for_each_token(content, language_id, config, |word| {
+        if dropped_reason(word, config).is_none() {
+            *counts.entry(word.to_owned()).or_insert(0) += 1;
+        }
+    });
+    counts
+}
+
+/// Calls `visit` with every token of `content` before filtering: the pattern matches, then
+/// their identifier parts and dotted names when those are enabled.
+fn for_each_token(content: &str, language_id: &str, config: &Config, mut visit: impl FnMut(&str)) {
+    // A byte order mark is encoding metadata, not part of the first word.
+    let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+    let matches = tokenize::find_words(config.word_pattern_for(language_id), content);
+    for m in &matches {
+        let word = &content[m.clone()];
+        let parts = if config.split_identifiers {
+            split_identifier(word)
+        } else {
+            Vec::new()
+        };
+        for word in std::iter::once(word).chain(parts) {
+            visit(word);
+        }
+    }
+    if config.include_dots {
+        for name in dotted_names(content, &matches) {
+            visit(name);
         }
     }
-    Ok(())
 }
 
-fn main() -> Result<()> {
-    init_logger();
-    info!("Starting LSP server");
-    let (connection, io_threads) = Connection::stdio();
+/// Why a token is left out of the word index, if it is.
+fn dropped_reason(word: &str, config: &Config) -> Option<&'static str> {
+    if config.exclude_numbers && is_number(word) {
+        Some("number")
+    } else if word.chars().count() < config.min_word_length {
+        Some("tooShort")
+    } else {
+        None
+    }
+}
 
-    let triggers: Vec<String> = ('A'..='Z')
-        .chain('a'..='z')
-        .map(|c| c.to_string())
-        .collect();
+/// Words of an open document: every word, or with `commentsOnly` naming its language, only
+/// those in comments and string literals.
+fn document_words(text: &str, language_id: &str, config: &Config) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for_each_document_token(text, language_id, config, |word| {
+        if dropped_reason(word, config).is_none() {
+            *counts.entry(word.to_owned()).or_insert(0) += 1;
+        }
+    });
+    counts
+}
 
-    let server_capabilities = serde_json::to_value(ServerCapabilities {
-        completion_provider: Some(CompletionOptions {
-            trigger_characters: Some(triggers),
-            ..Default::default()
-        }),
-        text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Kind(
-            TextDocumentSyncKind::FULL,
-        )),
-        ..Default::default()
-    })?;
+/// Calls `visit` with every token of an open document before filtering, following
+/// `commentsOnly` like [`document_words`].
+fn for_each_document_token(
+    text: &str,
+    language_id: &str,
+    config: &Config,
+    mut visit: impl FnMut(&str),
+) {
+    if !config.comments_only.iter().any(|id| id == language_id) {
+        return for_each_token(text, language_id, config, visit);
+    }
+    for range in comments::prose_ranges(text, language_id) {
+        for_each_token(&text[range], language_id, config, &mut visit);
+    }
+}
+
+/// How long a document must go unedited before its word index is rebuilt.
+const REINDEX_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// The longest a rebuild waits after the first edit it covers, so that typing without pause
+/// still gets indexed.
+const REINDEX_MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// An open document together with the word index derived from its text.
+struct Document {
+    text: String,
+    words: WordIndex,
+    /// The client's `languageId`, empty when the document was never opened.
+    language_id: String,
+    /// When set, `words` lags behind `text` and should be rebuilt at this instant.
+    reindex_at: Option<Instant>,
+    /// The first edit the pending rebuild covers.
+    edited_at: Option<Instant>,
+    /// When set, the published diagnostics lag behind `text` and are sent again at this
+    /// instant, along with the rebuild if one is scheduled.
+    diagnostics_at: Option<Instant>,
+    /// Tick of the last request or edit concerning this document, for LRU eviction.
+    last_used: u64,
+    /// `words` was dropped to save memory and is rebuilt when the document is used again.
+    evicted: bool,
+    /// Words that first appeared through edits, most recent last.
+    recent: VecDeque<String>,
+    /// Where the word completion last answered for starts, letting re-requests for its
+    /// incomplete list answer too.
+    completion_start: Option<Position>,
+    /// The candidates found for that word, which re-requests narrow down as the prefix grows
+    /// instead of searching every source again.
+    candidates: Option<Candidates>,
+}
+
+/// Words matching `prefix` in any completion source, before ranking and filtering.
+struct Candidates {
+    prefix: String,
+    words: Vec<String>,
+}
+
+/// How many newly typed words a document remembers for ranking.
+const MAX_RECENT_WORDS: usize = 100;
 
-    match connection.initialize(server_capabilities) {
-        Ok(initialize_result) => {
-            let params = serde_json::to_string(&initialize_result)?;
-            info!("Initialized with params: {}", params);
-            if let Err(e) = serve(connection) {
-                error!("{e:?}");
+/// A monotonically increasing counter ordering document accesses.
+fn tick() -> u64 {
+    static TICK: AtomicU64 = AtomicU64::new(0);
+    TICK.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Document {
+    fn new(text: String, language_id: String, config: &Config) -> Self {
+        let words = WordIndex::from(document_words(&text, &language_id, config));
+        Document {
+            text,
+            words,
+            language_id,
+            reindex_at: None,
+            edited_at: None,
+            diagnostics_at: None,
+            last_used: tick(),
+            evicted: false,
+            recent: VecDeque::new(),
+            completion_start: None,
+            candidates: None,
+        }
+    }
+
+    /// Rebuilds the index after edits, remembering the words that were not there before.
+    fn reindex_edited(&mut self, config: &Config, open_words: &mut WordIndex) {
+        let words = WordIndex::from(document_words(&self.text, &self.language_id, config));
+        for word in words.prefix_completions("") {
+            if self.words.count(&word) == 0 {
+                self.remember(word);
             }
         }
-        Err(err) => {
-            error!("Error initializing connection: {:?}", err);
-            return Ok(());
+        self.set_words(words, open_words);
+        self.forget_gone();
+    }
+
+    /// Applies an edit and updates the counts of the words on the lines it touches, rather
+    /// than rebuilding the whole index. Replacing the whole text, an index that already lags
+    /// behind, `commentsOnly` languages (a comment may open lines earlier) and word patterns
+    /// matching line breaks fall back to a rebuild once the edits settle.
+    fn apply_change(
+        &mut self,
+        change: TextDocumentContentChangeEvent,
+        config: &Config,
+        open_words: &mut WordIndex,
+    ) {
+        // Typing the word being completed keeps its candidates; other edits may change them.
+        let completing = change.range.is_some_and(|range| {
+            range.start.line == range.end.line
+                && self.completion_start.is_some_and(|start| {
+                    start.line == range.start.line && start.character <= range.start.character
+                })
+        });
+        if !completing {
+            self.candidates = None;
+        }
+        let incremental = self.reindex_at.is_none()
+            && !self.evicted
+            && !config.comments_only.contains(&self.language_id)
+            && !config.word_pattern_for(&self.language_id).is_match("\n");
+        let Some(range) = change.range.filter(|_| incremental) else {
+            text::apply_change(&mut self.text, change, config.position_encoding);
+            // Completion keeps answering from the previous words until the edits settle.
+            self.schedule_reindex();
+            return;
+        };
+        let start = position_to_offset(&self.text, range.start, config.position_encoding);
+        let end = position_to_offset(&self.text, range.end, config.position_encoding).max(start);
+        let line_start = self.text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.text[end..]
+            .find('\n')
+            .map_or(self.text.len(), |i| end + i);
+        let removed = load_words(&self.text[line_start..line_end], &self.language_id, config);
+        self.text.replace_range(start..end, &change.text);
+        let line_end = line_end - (end - start) + change.text.len();
+        let added = load_words(&self.text[line_start..line_end], &self.language_id, config);
+
+        let mut added = added.into_iter().collect::<Vec<_>>();
+        added.sort();
+        for (word, _) in &added {
+            if self.words.count(word) == 0 {
+                self.remember(word.clone());
+            }
+        }
+        for (word, count) in removed {
+            self.words.remove(&word, count);
+            open_words.remove(&word, count);
+        }
+        for (word, count) in added {
+            self.words.add(&word, count);
+            open_words.add(&word, count);
         }
+        self.forget_gone();
+    }
+
+    /// Records a word that first appeared through an edit.
+    fn remember(&mut self, word: String) {
+        if !self.recent.contains(&word) {
+            self.recent.push_back(word);
+        }
+        while self.recent.len() > MAX_RECENT_WORDS {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Drops the recent words no longer in the text, such as the prefixes a word passed
+    /// through while it was typed, so that they do not take up the slots of real ones.
+    fn forget_gone(&mut self) {
+        let words = &self.words;
+        self.recent.retain(|word| words.contains(word));
+    }
+
+    fn reindex(&mut self, config: &Config, open_words: &mut WordIndex) {
+        self.set_words(
+            WordIndex::from(document_words(&self.text, &self.language_id, config)),
+            open_words,
+        );
+    }
+
+    /// Drops the word index but keeps the text, which incremental sync still needs.
+    fn evict(&mut self, open_words: &mut WordIndex) {
+        self.set_words(WordIndex::default(), open_words);
+        self.evicted = true;
+    }
+
+    /// Adds the words to `open_words`, the summed words of every open document, once the
+    /// document is opened. Every later change to `words` keeps `open_words` up to date.
+    fn open(&self, open_words: &mut WordIndex) {
+        for (word, count) in self.words.iter() {
+            open_words.add(word, count);
+        }
+    }
+
+    /// Takes the words back out of `open_words` when the document closes.
+    fn close(self, open_words: &mut WordIndex) {
+        for (word, count) in self.words.iter() {
+            open_words.remove(word, count);
+        }
+    }
+
+    /// Replaces the index with one that is current for the text.
+    fn set_words(&mut self, words: WordIndex, open_words: &mut WordIndex) {
+        for (word, count) in self.words.iter() {
+            open_words.remove(word, count);
+        }
+        for (word, count) in words.iter() {
+            open_words.add(word, count);
+        }
+        self.words = words;
+        self.reindex_at = None;
+        self.edited_at = None;
+        self.evicted = false;
+    }
+
+    /// Pushes the rebuild back so that a burst of edits is indexed once, after it ends, but
+    /// no further than [`REINDEX_MAX_WAIT`] after the first edit of the burst.
+    fn schedule_reindex(&mut self) {
+        let now = Instant::now();
+        let first = *self.edited_at.get_or_insert(now);
+        self.reindex_at = Some((now + REINDEX_DEBOUNCE).min(first + REINDEX_MAX_WAIT));
     }
-    Ok(io_threads.join()?)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use lsp_types::{TextDocumentIdentifier, TextDocumentPositionParams};
-    use std::collections::HashMap;
+/// The earliest pending rebuild or diagnostics refresh, if any document has one scheduled.
+fn next_reindex(docs: &HashMap<Uri, Document>) -> Option<Instant> {
+    docs.values()
+        .flat_map(|doc| [doc.reindex_at, doc.diagnostics_at])
+        .flatten()
+        .min()
+}
 
-    #[test]
-    fn test_create_log_file() {
-        let temp_dir = std::env::temp_dir();
-        let file = create_log_file(&temp_dir).unwrap();
-        assert!(file.metadata().unwrap().is_file());
-        let log_path = temp_dir.join("lsp-word").join("lsp-word.log");
-        assert!(log_path.exists());
+/// Rebuilds every document whose quiet period has elapsed by `now`; returns how many were rebuilt.
+fn reindex_due(
+    docs: &mut HashMap<Uri, Document>,
+    open_words: &mut WordIndex,
+    config: &Config,
+    now: Instant,
+) -> usize {
+    let mut rebuilt = 0;
+    for doc in docs.values_mut() {
+        if doc.reindex_at.is_some_and(|at| at <= now) {
+            doc.reindex_edited(config, open_words);
+            rebuilt += 1;
+        }
     }
+    rebuilt
+}
 
-    #[test]
-    fn test_load_all_words_basic() {
-        let uri = "file:///test".parse::<Uri>().unwrap();
-        let mut docs = HashMap::new();
-        docs.insert(uri.clone(), "fn main() { let test = 1; }".to_string());
+/// Marks `uri` as the focused document, restoring its words if they were evicted, then evicts
+/// the least recently used indexes beyond `maxCachedDocuments`. The focused one is always kept.
+fn focus(
+    docs: &mut HashMap<Uri, Document>,
+    open_words: &mut WordIndex,
+    uri: &Uri,
+    config: &Config,
+) {
+    if let Some(doc) = docs.get_mut(uri) {
+        doc.last_used = tick();
+        if doc.evicted {
+            doc.reindex(config, open_words);
+        }
+    }
+    let Some(max) = config.max_cached_documents else {
+        return;
+    };
+    let focused = usize::from(docs.contains_key(uri));
+    let mut cached = docs
+        .iter_mut()
+        .filter(|(doc_uri, doc)| !doc.evicted && *doc_uri != uri)
+        .map(|(_, doc)| doc)
+        .collect::<Vec<_>>();
+    let excess = (cached.len() + focused).saturating_sub(max.max(1));
+    cached.sort_by_key(|doc| doc.last_used);
+    for doc in cached.into_iter().take(excess) {
+        doc.evict(open_words);
+    }
+}
 
-        let words = load_all_words(uri, &docs).unwrap();
-        let expected_words: HashSet<String> = ["fn", "main", "let", "test"]
+/// The part of the word at `position` before the cursor; see [`word_range`].
+fn word_prefix<'a>(
+    content: &'a str,
+    position: Position,
+    encoding: Encoding,
+    pattern: &Regex,
+) -> &'a str {
+    let offset = position_to_offset(content, position, encoding);
+    &content[word_range(content, offset, pattern).start..offset]
+}
+
+/// The whole word spanning `position`, including characters after the cursor.
+fn word_at<'a>(
+    content: &'a str,
+    position: Position,
+    encoding: Encoding,
+    pattern: &Regex,
+) -> &'a str {
+    &content[word_range(
+        content,
+        position_to_offset(content, position, encoding),
+        pattern,
+    )]
+}
+
+/// Byte range of the match of `pattern` spanning `offset`, empty when there is none. Words
+/// are found on the line of `offset` the way the tokenizer finds them, so that a pattern
+/// such as `[a-z][a-z-]*` also covers the `-` of a half-typed `font-`.
+fn word_range(content: &str, offset: usize, pattern: &Regex) -> std::ops::Range<usize> {
+    let line_start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[offset..]
+        .find('\n')
+        .map_or(content.len(), |i| offset + i);
+    pattern
+        .find_iter(&content[line_start..line_end])
+        .map(|m| line_start + m.start()..line_start + m.end())
+        .take_while(|range| range.start <= offset)
+        .find(|range| offset <= range.end)
+        .unwrap_or(offset..offset)
+}
+
+/// Keywords common to many languages, reported with `CompletionItemKind::KEYWORD`.
+const KEYWORDS: &[&str] = &[
+    "as",
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "def",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "fn",
+    "for",
+    "from",
+    "func",
+    "function",
+    "if",
+    "impl",
+    "import",
+    "in",
+    "interface",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "new",
+    "nil",
+    "null",
+    "package",
+    "pub",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "use",
+    "var",
+    "while",
+    "yield",
+];
+
+fn completion_kind(word: &str) -> CompletionItemKind {
+    if KEYWORDS.contains(&word) {
+        CompletionItemKind::KEYWORD
+    } else {
+        CompletionItemKind::TEXT
+    }
+}
+
+/// Ranges of every whole-word occurrence of `word` in `content`.
+fn word_occurrences(content: &str, language_id: &str, word: &str, config: &Config) -> Vec<Range> {
+    let lines = LineIndex::new(content, config.position_encoding);
+    config
+        .word_pattern_for(language_id)
+        .find_iter(content)
+        .filter(|m| m.as_str() == word)
+        .map(|m| lines.range(m.range()))
+        .collect()
+}
+
+/// Each distinct word with the range of its first occurrence, in document order. The words
+/// are those of the document's index, filtered like completions, stop words included.
+fn first_occurrences(content: &str, language_id: &str, config: &Config) -> Vec<(String, Range)> {
+    let lines = LineIndex::new(content, config.position_encoding);
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+    for_each_document_token(content, language_id, config, |word| {
+        if dropped_reason(word, config).is_none()
+            && !config.stop_words.contains(&word.to_lowercase())
+            && seen.insert(word.to_owned())
+        {
+            // Tokens are slices of `content`, so their address gives their offset in it.
+            let start = word.as_ptr() as usize - content.as_ptr() as usize;
+            words.push((start..start + word.len(), word.to_owned()));
+        }
+    });
+    // Identifier parts and dotted names are visited after the words they are found in.
+    words.sort_by_key(|(range, _)| range.start);
+    words
+        .into_iter()
+        .map(|(range, word)| (word, lines.range(range)))
+        .collect()
+}
+
+/// Scores `word` against the typed `pattern` as a case-insensitive subsequence; lower is better.
+/// Prefix matches score 0, so they always outrank scattered matches, which are penalized
+/// by how late they start and how many characters lie between the matched ones.
+fn fuzzy_score(word: &str, pattern: &str) -> Option<usize> {
+    let mut pattern_chars = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut matched = Vec::new();
+    for (i, c) in word.chars().flat_map(char::to_lowercase).enumerate() {
+        if pattern_chars.next_if_eq(&c).is_some() {
+            matched.push(i);
+        }
+    }
+    if pattern_chars.peek().is_some() {
+        return None;
+    }
+    let (Some(&first), Some(&last)) = (matched.first(), matched.last()) else {
+        return Some(0);
+    };
+    let gaps = last - first + 1 - matched.len();
+    if first == 0 && gaps == 0 {
+        Some(0)
+    } else {
+        Some(1 + first + gaps)
+    }
+}
+
+/// The documents completion offers the words of.
+fn completion_sources<'a>(
+    docs: &'a HashMap<Uri, Document>,
+    uri: &Uri,
+    config: &Config,
+) -> Vec<(&'a Uri, &'a Document)> {
+    if config.all_documents {
+        docs.iter().collect()
+    } else {
+        docs.get_key_value(uri).into_iter().collect()
+    }
+}
+
+/// Whether [`matching_words`] finds `word` for `prefix`.
+fn matches_prefix(word: &str, prefix: &str, config: &Config) -> bool {
+    if config.fuzzy {
+        fuzzy_score(word, prefix).is_some()
+    } else if config.case_sensitive {
+        word.starts_with(prefix)
+    } else {
+        word.to_lowercase().starts_with(&prefix.to_lowercase())
+    }
+}
+
+fn matching_words(index: &WordIndex, prefix: &str, config: &Config) -> Vec<String> {
+    if config.fuzzy {
+        let mut words = index.prefix_completions("");
+        words.retain(|word| fuzzy_score(word, prefix).is_some());
+        words
+    } else if config.case_sensitive {
+        index.prefix_completions(prefix)
+    } else {
+        index.prefix_completions_ignore_case(prefix)
+    }
+}
+
+/// For each word of `content`, how many lines its nearest occurrence is from `position`.
+/// The word under the cursor itself is not an occurrence.
+fn line_distances(
+    content: &str,
+    language_id: &str,
+    position: Position,
+    config: &Config,
+) -> HashMap<String, u32> {
+    let cursor = position_to_offset(content, position, config.position_encoding);
+    let lines = LineIndex::new(content, config.position_encoding);
+    let mut distances = HashMap::new();
+    for m in config.word_pattern_for(language_id).find_iter(content) {
+        if m.range().contains(&cursor) || m.end() == cursor {
+            continue;
+        }
+        let distance = lines.position(m.start()).line.abs_diff(position.line);
+        distances
+            .entry(m.as_str().to_owned())
+            .and_modify(|nearest: &mut u32| *nearest = (*nearest).min(distance))
+            .or_insert(distance);
+    }
+    distances
+}
+
+/// A snippet with a tab stop on every underscore-separated part after the first,
+/// e.g. `user_${1:id}_${2:map}`; `None` unless the word has at least two parts.
+fn compound_snippet(word: &str) -> Option<String> {
+    let mut snippet = String::new();
+    let mut parts = 0;
+    for (i, part) in word.split('_').enumerate() {
+        if i > 0 {
+            snippet.push('_');
+        }
+        if part.is_empty() {
+            continue;
+        }
+        // `$`, `}` and `\` are snippet syntax and must be escaped inside text.
+        let escaped = part
+            .replace('\\', "\\\\")
+            .replace('$', "\\$")
+            .replace('}', "\\}");
+        if parts == 0 {
+            snippet.push_str(&escaped);
+        } else {
+            snippet.push_str(&format!("${{{parts}:{escaped}}}"));
+        }
+        parts += 1;
+    }
+    (parts > 1).then_some(snippet)
+}
+
+/// Collapses words that differ only in case into one entry with their counts summed, spelled the
+/// way it occurs most often; ties go to the all-lowercase spelling, then alphabetically.
+fn merge_casing(words: HashMap<String, usize>) -> HashMap<String, usize> {
+    let mut variants: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+    for (word, count) in words {
+        variants
+            .entry(word.to_lowercase())
+            .or_default()
+            .push((word, count));
+    }
+    variants
+        .into_iter()
+        .filter_map(|(lower, spellings)| {
+            let total = spellings.iter().map(|(_, count)| count).sum();
+            let (word, _) = spellings
+                .into_iter()
+                .max_by_key(|(word, count)| (*count, *word == lower, Reverse(word.clone())))?;
+            Some((word, total))
+        })
+        .collect()
+}
+
+/// Gives every word the summed count of the words sharing its stem, so that `run`, `runs` and
+/// `running` rank together while each is still offered as written.
+fn group_stems(words: HashMap<String, usize>) -> HashMap<String, usize> {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for (word, count) in &words {
+        *totals.entry(stem::stem(word)).or_insert(0) += count;
+    }
+    words
+        .into_keys()
+        .map(|word| {
+            let total = totals[&stem::stem(&word)];
+            (word, total)
+        })
+        .collect()
+}
+
+/// Item properties that may be moved into `CompletionList.itemDefaults`.
+const ITEM_DEFAULT_PROPERTIES: [&str; 4] = [
+    "commitCharacters",
+    "insertTextFormat",
+    "insertTextMode",
+    "data",
+];
+
+/// Serializes `list`, moving every property that all items share and that the client supports
+/// in `itemDefaults` out of the items. A shared `textEdit` range becomes `editRange`, each item
+/// keeping only its `textEditText` where that differs from the label.
+/// `lsp_types::CompletionList` has no `itemDefaults` field, hence the JSON.
+fn completion_list_value(list: CompletionList, supported: &[String]) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(list)?;
+    let Some(items) = value["items"]
+        .as_array_mut()
+        .filter(|items| !items.is_empty())
+    else {
+        return Ok(value);
+    };
+    let mut defaults = serde_json::Map::new();
+    for property in ITEM_DEFAULT_PROPERTIES {
+        if !supported.iter().any(|name| name == property) {
+            continue;
+        }
+        let shared = items[0].get(property).cloned();
+        if shared.is_none()
+            || items
+                .iter()
+                .any(|item| item.get(property) != shared.as_ref())
+        {
+            continue;
+        }
+        for item in items
+            .iter_mut()
+            .filter_map(serde_json::Value::as_object_mut)
+        {
+            item.remove(property);
+        }
+        defaults.insert(property.to_string(), shared.unwrap());
+    }
+    if supported.iter().any(|name| name == "editRange")
+        && let Some(range) = items[0].pointer("/textEdit/range").cloned()
+        && items
             .iter()
-            .cloned()
-            .map(String::from)
-            .collect();
+            .all(|item| item.pointer("/textEdit/range") == Some(&range))
+    {
+        for item in items
+            .iter_mut()
+            .filter_map(serde_json::Value::as_object_mut)
+        {
+            let Some(mut edit) = item.remove("textEdit") else {
+                continue;
+            };
+            if item.get("label") != edit.get("newText") {
+                item.insert("textEditText".to_string(), edit["newText"].take());
+            }
+        }
+        defaults.insert("editRange".to_string(), range);
+    }
+    if !defaults.is_empty() {
+        value["itemDefaults"] = serde_json::Value::Object(defaults);
+    }
+    Ok(value)
+}
 
-        assert_eq!(words, expected_words);
+/// `Diagnostic::code` of the hints for words missing from the dictionary.
+const UNKNOWN_WORD_CODE: &str = "unknown-word";
+
+/// Hints for the words of `text` that the dictionary does not list, either as written or
+/// lowercased. Nothing is flagged unless `spellCheck` is on and a dictionary is loaded.
+fn spelling_diagnostics(text: &str, language_id: &str, config: &Config) -> Vec<Diagnostic> {
+    if !config.spell_check || config.dictionary.words.is_empty() {
+        return Vec::new();
     }
+    let known = |word: &str| {
+        config.dictionary.contains(word) || config.dictionary.contains(&word.to_lowercase())
+    };
+    let lines = LineIndex::new(text, config.position_encoding);
+    config
+        .word_pattern_for(language_id)
+        .find_iter(text)
+        .filter(|m| !is_number(m.as_str()) && !known(m.as_str()))
+        .map(|m| Diagnostic {
+            range: lines.range(m.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(UNKNOWN_WORD_CODE.to_string())),
+            source: Some(env!("CARGO_PKG_NAME").to_string()),
+            message: format!("Unknown word: {}", m.as_str()),
+            // Names the word for the quick fix adding it to the dictionary.
+            data: Some(m.as_str().into()),
+            ..Default::default()
+        })
+        .collect()
+}
 
-    #[test]
-    fn test_load_all_words_empty() {
-        let uri = "file:///test".parse::<Uri>().unwrap();
-        let mut docs = HashMap::new();
-        docs.insert(uri.clone(), "".to_string());
+/// `Diagnostic::code` of the warnings for repeated words.
+const DUPLICATE_WORD_CODE: &str = "duplicate-word";
 
-        let words = load_all_words(uri, &docs).unwrap();
-        assert!(words.is_empty());
+/// Warnings for words of `text` repeated right after themselves with only whitespace between,
+/// in any case, such as "the the". In programming languages only comments and string literals
+/// are checked.
+fn duplicate_word_diagnostics(text: &str, language_id: &str, config: &Config) -> Vec<Diagnostic> {
+    if !config.lint_duplicate_words {
+        return Vec::new();
+    }
+    let ranges = if keywords::language_keywords(language_id).is_empty() {
+        std::iter::once(0..text.len()).collect()
+    } else {
+        comments::prose_ranges(text, language_id)
+    };
+    let lines = LineIndex::new(text, config.position_encoding);
+    let mut diagnostics = Vec::new();
+    for range in ranges {
+        let offset = range.start;
+        let words = config
+            .word_pattern_for(language_id)
+            .find_iter(&text[range])
+            .filter(|m| !is_number(m.as_str()))
+            .collect::<Vec<_>>();
+        for pair in words.windows(2) {
+            let (first, second) = (pair[0], pair[1]);
+            let between = &text[offset + first.end()..offset + second.start()];
+            if !between.is_empty()
+                && between.chars().all(char::is_whitespace)
+                && first.as_str().to_lowercase() == second.as_str().to_lowercase()
+            {
+                diagnostics.push(Diagnostic {
+                    range: lines.range(offset + second.start()..offset + second.end()),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(DUPLICATE_WORD_CODE.to_string())),
+                    source: Some(env!("CARGO_PKG_NAME").to_string()),
+                    message: format!("Repeated word: {}", second.as_str()),
+                    ..Default::default()
+                });
+            }
+        }
     }
+    diagnostics
+}
 
-    #[test]
-    fn test_load_all_words_special_chars() {
-        let uri = "file:///test".parse::<Uri>().unwrap();
-        let mut docs = HashMap::new();
-        docs.insert(uri.clone(), "let x1 = 42; // @#$%".to_string());
+/// Every diagnostic published for a document.
+fn document_diagnostics(doc: &Document, config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = spelling_diagnostics(&doc.text, &doc.language_id, config);
+    diagnostics.extend(duplicate_word_diagnostics(
+        &doc.text,
+        &doc.language_id,
+        config,
+    ));
+    diagnostics
+}
 
-        let words = load_all_words(uri, &docs).unwrap();
-        let expected_words: HashSet<String> =
-            ["let", "x1"].iter().cloned().map(String::from).collect();
+fn publish_diagnostics(uri: &Uri, diagnostics: Vec<Diagnostic>) -> Notification {
+    Notification::new(
+        notification::PublishDiagnostics::METHOD.to_string(),
+        PublishDiagnosticsParams::new(uri.clone(), diagnostics, None),
+    )
+}
 
-        assert_eq!(words, expected_words);
+/// `textDocument/publishDiagnostics` notifications due after handling a `method` message about
+/// `uri`. Closing a document clears its diagnostics, and a configuration change or a command,
+/// which may have added to the dictionary, refreshes every document when diagnostics are or
+/// were on. Edits are published by [`Backend::due_diagnostics`] once they settle.
+fn diagnostic_notifications(
+    method: &str,
+    uri: Option<Uri>,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+    was_checking: bool,
+) -> Vec<Notification> {
+    match (method, uri) {
+        (notification::DidChangeConfiguration::METHOD | request::ExecuteCommand::METHOD, _)
+            if was_checking || config.publishes_diagnostics() =>
+        {
+            docs.iter()
+                .map(|(uri, doc)| publish_diagnostics(uri, document_diagnostics(doc, config)))
+                .collect()
+        }
+        (notification::DidCloseTextDocument::METHOD, Some(uri))
+            if config.publishes_diagnostics() =>
+        {
+            vec![publish_diagnostics(&uri, Vec::new())]
+        }
+        (
+            notification::DidOpenTextDocument::METHOD | notification::DidSaveTextDocument::METHOD,
+            Some(uri),
+        ) if config.publishes_diagnostics() => docs
+            .get(&uri)
+            .map(|doc| publish_diagnostics(&uri, document_diagnostics(doc, config)))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
     }
+}
 
-    #[test]
-    fn test_create_completion_response() {
-        let uri = "file:///test".parse::<Uri>().unwrap();
-        let mut docs = HashMap::new();
-        docs.insert(uri.clone(), "fn main() { let test = 1; }".to_string());
+/// "1 occurrence", "3 occurrences", and so on.
+fn occurrences_label(count: usize) -> String {
+    let noun = if count == 1 {
+        "occurrence"
+    } else {
+        "occurrences"
+    };
+    format!("{count} {noun}")
+}
 
-        let req = Request {
-            id: 1.into(),
-            method: "textDocument/completion".to_string(),
-            params: serde_json::to_value(CompletionParams {
-                text_document_position: TextDocumentPositionParams {
-                    text_document: TextDocumentIdentifier { uri: uri.clone() },
-                    position: lsp_types::Position {
-                        line: 0,
-                        character: 0,
-                    },
-                },
-                work_done_progress_params: Default::default(),
-                partial_result_params: Default::default(),
-                context: None,
-            })
-            .unwrap(),
-        };
+/// The last segment of a document's path, such as `main.rs`.
+fn file_name(uri: &Uri) -> String {
+    let path = match workspace::uri_to_path(uri) {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None => uri.path().as_str().to_string(),
+    };
+    path.rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
 
-        let response = create_completion_response(req, &docs).unwrap();
-        if let Message::Response(resp) = response {
-            assert!(resp.response_result.is_ok());
-        } else {
-            panic!("Expected a response message");
+/// For each of `words`, the name of the file it occurs in most often, among the documents
+/// completion drew from and the workspace files. Ties go to the requesting document, then to
+/// the first name. Every source is visited once for all the words, and only named when it
+/// contains one of them.
+fn word_sources(
+    words: &[&str],
+    uri: &Uri,
+    sources: &[(&Uri, &Document)],
+    workspace: &WorkspaceIndex,
+) -> HashMap<String, String> {
+    let mut best = HashMap::new();
+    for (doc_uri, doc) in sources {
+        let mut name = None;
+        for &word in words {
+            let count = doc.words.count(word);
+            if count > 0 {
+                let name = name.get_or_insert_with(|| file_name(doc_uri));
+                offer_source(&mut best, word, count, *doc_uri == uri, name);
+            }
         }
     }
+    for (path, counts) in workspace.files() {
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        for &word in words {
+            if let Some(&count) = counts.get(word) {
+                offer_source(&mut best, word, count, false, &name);
+            }
+        }
+    }
+    best.into_iter()
+        .map(|(word, (_, _, name))| (word.to_owned(), name))
+        .collect()
+}
+
+/// Records `name` as the source of `word` if it beats the best one so far, as ordered by
+/// [`word_sources`].
+fn offer_source<'a>(
+    best: &mut HashMap<&'a str, (usize, bool, String)>,
+    word: &'a str,
+    count: usize,
+    current: bool,
+    name: &str,
+) {
+    let better = best
+        .get(word)
+        .is_none_or(|(best_count, best_current, best_name)| {
+            (count, current, Reverse(name))
+                > (*best_count, *best_current, Reverse(best_name.as_str()))
+        });
+    if better {
+        best.insert(word, (count, current, name.to_owned()));
+    }
+}
+
+fn create_completion_response(
+    req: Request,
+    docs: &mut HashMap<Uri, Document>,
+    workspace: &WorkspaceIndex,
+    open_words: &WordIndex,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<CompletionParams>(req.params)?;
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    // The prefix starts where its bytes do, also when the client's position lies past the end
+    // of the line and is clamped.
+    let (prefix, current, start) = docs
+        .get(&uri)
+        .map(|doc| {
+            let pattern = config.word_pattern_for(&doc.language_id);
+            let prefix = word_prefix(&doc.text, position, config.position_encoding, pattern);
+            let offset = position_to_offset(&doc.text, position, config.position_encoding);
+            let start =
+                LineIndex::new(&doc.text, config.position_encoding).position(offset - prefix.len());
+            (
+                prefix.to_owned(),
+                word_at(&doc.text, position, config.position_encoding, pattern).to_owned(),
+                start,
+            )
+        })
+        .unwrap_or((String::new(), String::new(), position));
+    // Explicit invocation always answers, and so do re-requests for an incomplete list while
+    // the word it was for is edited, even once backspace makes it shorter. Trigger characters,
+    // whose prefix includes the character when it is part of a word, and clients sending no
+    // context wait for a long enough prefix.
+    let rerequest = docs
+        .get(&uri)
+        .is_some_and(|doc| doc.completion_start == Some(start));
+    let answer = match params.context.map(|context| context.trigger_kind) {
+        Some(CompletionTriggerKind::INVOKED) => true,
+        Some(CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS) if rerequest => true,
+        _ => prefix.chars().count() >= config.min_prefix_length,
+    };
+    let cached = docs.get_mut(&uri).and_then(|doc| {
+        doc.completion_start = answer.then_some(start);
+        doc.candidates
+            .take()
+            .filter(|cached| answer && rerequest && prefix.starts_with(&cached.prefix))
+    });
+    if !answer {
+        let empty = CompletionResponse::List(CompletionList {
+            is_incomplete: true,
+            items: Vec::new(),
+        });
+        return Ok(Message::Response(Response::new_ok(req.id, empty)));
+    }
+    let candidates = match cached {
+        // A longer prefix only ever matches fewer words.
+        Some(cached) => cached
+            .words
+            .into_iter()
+            .filter(|word| matches_prefix(word, &prefix, config))
+            .collect(),
+        None => {
+            let mut words = completion_sources(docs, &uri, config)
+                .into_iter()
+                .map(|(_, doc)| &doc.words)
+                .chain([
+                    workspace.words(),
+                    workspace.cached_words(),
+                    &config.dictionary.words,
+                    &config.tags,
+                    &config.added_words,
+                ])
+                .flat_map(|index| matching_words(index, &prefix, config))
+                .collect::<Vec<_>>();
+            words.sort();
+            words.dedup();
+            words
+        }
+    };
+    if let Some(doc) = docs.get_mut(&uri) {
+        doc.candidates = Some(Candidates {
+            prefix: prefix.clone(),
+            words: candidates.clone(),
+        });
+    }
+    let docs = &*docs;
+    let doc = docs.get(&uri);
+    let sources = completion_sources(docs, &uri, config);
+    let mut words = HashMap::new();
+    for word in candidates {
+        let count = sources
+            .iter()
+            .map(|(_, doc)| doc.words.count(&word))
+            .sum::<usize>()
+            + workspace.words().count(&word);
+        // Frequencies from the previous session only count until the word turns up in this one.
+        let count = if count > 0 {
+            count
+        } else {
+            workspace.cached_words().count(&word)
+        };
+        // Cached candidates may have disappeared from every source since.
+        let known = count > 0
+            || [
+                workspace.cached_words(),
+                &config.dictionary.words,
+                &config.tags,
+                &config.added_words,
+            ]
+            .iter()
+            .any(|index| index.contains(&word));
+        if known {
+            words.insert(word, count);
+        }
+    }
+    // The word being typed counts as one occurrence of itself; only offer it if it appears elsewhere.
+    if let Some(count) = words.get_mut(&current).filter(|_| !prefix.is_empty()) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            words.remove(&current);
+        }
+    }
+    if config.exclude_keywords {
+        let keywords = doc.map_or(&[][..], |doc| keywords::language_keywords(&doc.language_id));
+        words.retain(|word, _| !keywords.contains(&word.as_str()));
+    }
+    if !config.stop_words.is_empty() {
+        words.retain(|word, _| !config.stop_words.contains(&word.to_lowercase()));
+    }
+    if config.merge_casing {
+        words = merge_casing(words);
+    }
+    if config.prose {
+        words = group_stems(words);
+    }
+    let distances = doc
+        .map(|doc| line_distances(&doc.text, &doc.language_id, position, config))
+        .unwrap_or_default();
+    let recency = |word: &String| doc.and_then(|doc| doc.recent.iter().position(|w| w == word));
+    let mut words = words.into_iter().collect::<Vec<_>>();
+    // Best fuzzy match first, then the most recently typed, then nearest to the cursor, then most
+    // frequent; ties are broken alphabetically to keep the order stable. Words only found
+    // elsewhere count as farthest. When only the current document is searched, frequency blends
+    // its counts with those across every open document, so that words common to the project
+    // still rank higher; `allDocuments` already counts them all.
+    // With `ranking`, a weighted score of the same signals comes right after the fuzzy match,
+    // leaving the order above to break its ties.
+    let open_count = |word: &str| {
+        if config.all_documents {
+            0
+        } else {
+            open_words.count(word)
+        }
+    };
+    let max_frequency = words
+        .iter()
+        .map(|(word, count)| count + open_count(word))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let recent_len = doc.map_or(0, |doc| doc.recent.len());
+    words.sort_by_cached_key(|(word, count)| {
+        let score = if config.fuzzy {
+            fuzzy_score(word, &prefix).unwrap_or(usize::MAX)
+        } else {
+            0
+        };
+        let recency = recency(word);
+        let distance = distances.get(word).copied().unwrap_or(u32::MAX);
+        let frequency = *count + open_count(word);
+        let weighted = config.ranking.map_or(0.0, |ranking| {
+            let frequency = frequency as f64 / max_frequency as f64;
+            let recency = recency.map_or(0.0, |i| (i + 1) as f64 / recent_len as f64);
+            let proximity = if distance == u32::MAX {
+                0.0
+            } else {
+                1.0 / (f64::from(distance) + 1.0)
+            };
+            ranking.frequency * frequency
+                + ranking.recency * recency
+                + ranking.proximity * proximity
+        });
+        (
+            score,
+            // Non-negative floats order like their bits.
+            Reverse(weighted.to_bits()),
+            Reverse(recency),
+            distance,
+            Reverse(frequency),
+            word.clone(),
+        )
+    });
+    let is_incomplete = words.len() > config.max_completion_items;
+    words.truncate(config.max_completion_items);
+    // The ranking above still decides which words survive truncation.
+    if config.sort_mode == SortMode::Alphabetical {
+        words.sort_by_cached_key(|(word, _)| (word.to_lowercase(), word.clone()));
+    }
+    // Items replace the typed prefix, so clients that do not filter cannot duplicate it.
+    let prefix_range = Range::new(start, position);
+    let commit_characters = (config.commit_characters_support
+        && !config.commit_characters.is_empty())
+    .then(|| config.commit_characters.clone());
+    let width = words.len().to_string().len().max(4);
+    let mut source_names = if config.show_source {
+        let words = words
+            .iter()
+            .map(|(word, _)| word.as_str())
+            .collect::<Vec<_>>();
+        word_sources(&words, &uri, &sources, workspace)
+    } else {
+        HashMap::new()
+    };
+    let list = CompletionList {
+        is_incomplete,
+        items: words
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (word, count))| {
+                let snippet = config
+                    .snippet_completions
+                    .then(|| compound_snippet(&word))
+                    .flatten();
+                let insert_text_format = snippet.as_ref().map(|_| InsertTextFormat::SNIPPET);
+                let edit = TextEdit::new(prefix_range, snippet.unwrap_or_else(|| word.clone()));
+                let label_details =
+                    source_names
+                        .remove(&word)
+                        .map(|name| CompletionItemLabelDetails {
+                            detail: None,
+                            description: Some(name),
+                        });
+                CompletionItem {
+                    kind: Some(completion_kind(&word)),
+                    label: word.clone(),
+                    label_details,
+                    detail: (count > 0).then(|| occurrences_label(count)),
+                    filter_text: Some(word),
+                    sort_text: (config.sort_mode != SortMode::None)
+                        .then(|| format!("{rank:0width$}")),
+                    insert_text_format,
+                    text_edit: Some(CompletionTextEdit::Edit(edit)),
+                    commit_characters: commit_characters.clone(),
+                    // Lets completionItem/resolve look in the requesting document first.
+                    data: serde_json::to_value(&uri).ok(),
+                    ..Default::default()
+                }
+            })
+            .collect(),
+    };
+    let list = completion_list_value(list, &config.completion_item_defaults)?;
+    Ok(Message::Response(Response::new_ok(req.id, list)))
+}
+
+/// Attaches the first line where the item's word appears, with `completionContextLines` lines
+/// around it, preferring the document that asked for completion. Items whose word is no longer
+/// in any open document come back unchanged.
+fn create_completion_resolve_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let mut item = serde_json::from_value::<CompletionItem>(req.params)?;
+    let origin = item
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<Uri>(data).ok());
+    let mut sources = docs.iter().collect::<Vec<_>>();
+    sources.sort_by_key(|(uri, _)| (Some(*uri) != origin.as_ref(), uri.as_str()));
+    let context = sources.into_iter().find_map(|(_, doc)| {
+        let range = word_occurrences(&doc.text, &doc.language_id, &item.label, config)
+            .into_iter()
+            .next()?;
+        let lines = LineIndex::new(&doc.text, config.position_encoding);
+        let around = config.completion_context_lines;
+        let last_line = lines.line_count().saturating_sub(1);
+        let block = (range.start.line.saturating_sub(around)
+            ..=range.start.line.saturating_add(around).min(last_line))
+            .filter_map(|line| lines.line(line))
+            .collect::<Vec<_>>();
+        Some(dedent(&block))
+    });
+    if let Some(context) = context {
+        item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```\n{context}\n```"),
+        }));
+    }
+    Ok(Message::Response(Response::new_ok(req.id, item)))
+}
+
+/// `lines` joined without their common indentation, trailing whitespace, or blank lines at
+/// either end.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).count())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            let start = line
+                .char_indices()
+                .nth(indent)
+                .map_or(line.len(), |(i, _)| i);
+            line[start..].trim_end()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+fn create_hover_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<HoverParams>(req.params)?;
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+    let hover = docs.get(&uri).and_then(|doc| {
+        let word = word_at(
+            &doc.text,
+            position,
+            config.position_encoding,
+            config.word_pattern_for(&doc.language_id),
+        );
+        let count = config
+            .word_pattern_for(&doc.language_id)
+            .find_iter(&doc.text)
+            .filter(|m| m.as_str() == word)
+            .count();
+        (count > 0).then(|| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("`{word}` — {}", occurrences_label(count)),
+            }),
+            range: None,
+        })
+    });
+    Ok(Message::Response(Response::new_ok(req.id, hover)))
+}
+
+fn create_document_highlight_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<DocumentHighlightParams>(req.params)?;
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+    let highlights = docs.get(&uri).and_then(|doc| {
+        let word = word_at(
+            &doc.text,
+            position,
+            config.position_encoding,
+            config.word_pattern_for(&doc.language_id),
+        );
+        (!word.is_empty()).then(|| {
+            word_occurrences(&doc.text, &doc.language_id, word, config)
+                .into_iter()
+                .map(|range| DocumentHighlight {
+                    range,
+                    kind: Some(DocumentHighlightKind::TEXT),
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    Ok(Message::Response(Response::new_ok(req.id, highlights)))
+}
+
+fn create_references_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<ReferenceParams>(req.params)?;
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    // There are no declarations to tell apart, so `include_declaration` makes no difference.
+    let locations = docs.get(&uri).and_then(|doc| {
+        let word = word_at(
+            &doc.text,
+            position,
+            config.position_encoding,
+            config.word_pattern_for(&doc.language_id),
+        );
+        (!word.is_empty()).then(|| {
+            let mut sources = docs.iter().collect::<Vec<_>>();
+            sources.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+            sources
+                .into_iter()
+                .flat_map(|(uri, doc)| {
+                    word_occurrences(&doc.text, &doc.language_id, word, config)
+                        .into_iter()
+                        .map(|range| Location::new(uri.clone(), range))
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    Ok(Message::Response(Response::new_ok(req.id, locations)))
+}
+
+fn create_rename_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<RenameParams>(req.params)?;
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    let edit = docs.get(&uri).and_then(|doc| {
+        let word = word_at(
+            &doc.text,
+            position,
+            config.position_encoding,
+            config.word_pattern_for(&doc.language_id),
+        );
+        (!word.is_empty()).then(|| {
+            let sources = if config.rename_all_documents {
+                docs.iter().collect::<Vec<_>>()
+            } else {
+                vec![(&uri, doc)]
+            };
+            let changes = sources
+                .into_iter()
+                .filter_map(|(uri, doc)| {
+                    let edits = word_occurrences(&doc.text, &doc.language_id, word, config)
+                        .into_iter()
+                        .map(|range| TextEdit::new(range, params.new_name.clone()))
+                        .collect::<Vec<_>>();
+                    (!edits.is_empty()).then(|| (uri.clone(), edits))
+                })
+                .collect();
+            WorkspaceEdit::new(changes)
+        })
+    });
+    Ok(Message::Response(Response::new_ok(req.id, edit)))
+}
+
+fn create_document_symbol_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<DocumentSymbolParams>(req.params)?;
+    let symbols = docs.get(&params.text_document.uri).map(|doc| {
+        let symbols = first_occurrences(&doc.text, &doc.language_id, config)
+            .into_iter()
+            .map(|(name, range)| {
+                #[allow(deprecated)]
+                DocumentSymbol {
+                    name,
+                    detail: None,
+                    kind: SymbolKind::VARIABLE,
+                    tags: None,
+                    deprecated: None,
+                    range,
+                    selection_range: range,
+                    children: None,
+                }
+            })
+            .collect();
+        DocumentSymbolResponse::Nested(symbols)
+    });
+    Ok(Message::Response(Response::new_ok(req.id, symbols)))
+}
+
+/// Expands each position to its word, then its line, then the whole document. Positions
+/// outside a word start from the line, and a range no larger than its child is skipped.
+fn create_selection_range_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<SelectionRangeParams>(req.params)?;
+    let ranges = docs.get(&params.text_document.uri).map(|doc| {
+        let text = &doc.text;
+        let lines = LineIndex::new(text, config.position_encoding);
+        params
+            .positions
+            .iter()
+            .map(|&position| {
+                let offset = position_to_offset(text, position, config.position_encoding);
+                let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+                let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+                let line_end = line_start + text[line_start..line_end].trim_end_matches('\r').len();
+                let candidates = [
+                    word_range(text, offset, config.word_pattern_for(&doc.language_id)),
+                    line_start..line_end,
+                    0..text.len(),
+                ];
+                let mut chain: Vec<std::ops::Range<usize>> = Vec::new();
+                for range in candidates.into_iter().filter(|range| !range.is_empty()) {
+                    if chain.last().is_none_or(|last| range.len() > last.len()) {
+                        chain.push(range);
+                    }
+                }
+                // An empty document still answers with the position itself.
+                let innermost = SelectionRange {
+                    range: Range::new(position, position),
+                    parent: None,
+                };
+                chain
+                    .into_iter()
+                    .rev()
+                    .fold(None, |parent, range| {
+                        Some(SelectionRange {
+                            range: lines.range(range),
+                            parent: parent.map(Box::new),
+                        })
+                    })
+                    .unwrap_or(innermost)
+            })
+            .collect::<Vec<_>>()
+    });
+    Ok(Message::Response(Response::new_ok(req.id, ranges)))
+}
+
+/// Byte ranges of the block comments and balanced `{ ... }` blocks of `text`, each flagged
+/// whether it is a comment. Braces inside comments and string literals of `language_id`
+/// are skipped, unmatched ones ignored.
+fn fold_spans(text: &str, language_id: &str) -> Vec<(std::ops::Range<usize>, bool)> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut open = Vec::new();
+    let mut code = Vec::new();
+    let mut code_start = 0;
+    for span in comments::spans(text, language_id) {
+        code.push(code_start..span.range.start);
+        code_start = span.range.end;
+        if span.is_block_comment {
+            spans.push((span.range, true));
+        }
+    }
+    code.push(code_start..text.len());
+    for i in code.into_iter().flatten() {
+        match bytes[i] {
+            b'{' => open.push(i),
+            b'}' => {
+                if let Some(start) = open.pop() {
+                    spans.push((start..i + 1, false));
+                }
+            }
+            _ => {}
+        }
+    }
+    spans.sort_by_key(|(range, _)| range.start);
+    spans
+}
+
+fn create_folding_range_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<FoldingRangeParams>(req.params)?;
+    let ranges = docs.get(&params.text_document.uri).map(|doc| {
+        let lines = LineIndex::new(&doc.text, config.position_encoding);
+        fold_spans(&doc.text, &doc.language_id)
+            .into_iter()
+            .filter_map(|(span, is_comment)| {
+                let range = lines.range(span);
+                (range.end.line > range.start.line).then(|| FoldingRange {
+                    start_line: range.start.line,
+                    end_line: range.end.line,
+                    kind: is_comment.then_some(FoldingRangeKind::Comment),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+    Ok(Message::Response(Response::new_ok(req.id, ranges)))
+}
+
+/// Edits deleting the whitespace at the end of each line of `text`, leaving the line breaks,
+/// `\n`, `\r\n` or `\r`, as they are.
+fn trailing_whitespace_edits(text: &str, encoding: Encoding) -> Vec<TextEdit> {
+    let lines = LineIndex::new(text, encoding);
+    (0..lines.line_count())
+        .filter_map(|line| {
+            let content = lines.line(line)?;
+            let trimmed = content.trim_end();
+            (trimmed.len() < content.len()).then(|| {
+                let start = Position::new(line, encoding.len(trimmed) as u32);
+                let end = Position::new(line, encoding.len(content) as u32);
+                TextEdit::new(Range::new(start, end), String::new())
+            })
+        })
+        .collect()
+}
+
+/// Trailing whitespace removed before saving, with `trimTrailingWhitespaceOnSave`.
+fn create_will_save_wait_until_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<WillSaveTextDocumentParams>(req.params)?;
+    let edits = docs
+        .get(&params.text_document.uri)
+        .filter(|_| config.trim_trailing_whitespace_on_save)
+        .map(|doc| trailing_whitespace_edits(&doc.text, config.position_encoding))
+        .unwrap_or_default();
+    Ok(Message::Response(Response::new_ok(req.id, edits)))
+}
+
+const MAX_WORKSPACE_SYMBOLS: usize = 100;
+
+/// Words of the open documents matching the query. Documents whose file was indexed from a
+/// workspace folder name that folder as the container, which tells multi-root workspaces apart.
+fn create_workspace_symbol_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    workspace: &WorkspaceIndex,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<WorkspaceSymbolParams>(req.params)?;
+    let query = params.query.to_lowercase();
+    let mut sources = docs.iter().collect::<Vec<_>>();
+    sources.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    let symbols = sources
+        .into_iter()
+        .flat_map(|(uri, doc)| {
+            let folder = workspace::uri_to_path(uri)
+                .and_then(|path| workspace.file_root(&path).map(folder_name));
+            first_occurrences(&doc.text, &doc.language_id, config)
+                .into_iter()
+                .filter(|(name, _)| name.to_lowercase().contains(&query))
+                .map(move |(name, range)| {
+                    #[allow(deprecated)]
+                    SymbolInformation {
+                        name,
+                        kind: SymbolKind::VARIABLE,
+                        tags: None,
+                        deprecated: None,
+                        location: Location::new(uri.clone(), range),
+                        container_name: folder.clone(),
+                    }
+                })
+        })
+        .take(MAX_WORKSPACE_SYMBOLS)
+        .collect();
+    Ok(Message::Response(Response::new_ok(
+        req.id,
+        WorkspaceSymbolResponse::Flat(symbols),
+    )))
+}
+
+/// The last component of a workspace folder, or the whole path for a root like `/`.
+fn folder_name(root: &Path) -> String {
+    root.file_name().map_or_else(
+        || root.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Command re-reading the workspace and re-indexing every open document.
+const REINDEX_COMMAND: &str = "lsp-word.reindex";
+/// Command appending its argument to the dictionary file, offered by the spelling quick fix.
+const ADD_TO_DICTIONARY_COMMAND: &str = "lsp-word.addToDictionary";
+
+/// Runs a command from [`server_capabilities`], answering with a summary of what it did.
+fn create_execute_command_response(
+    req: Request,
+    docs: &mut HashMap<Uri, Document>,
+    workspace: &mut WorkspaceIndex,
+    open_words: &mut WordIndex,
+    config: &mut Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<ExecuteCommandParams>(req.params)?;
+    let result = match params.command.as_str() {
+        REINDEX_COMMAND => {
+            workspace.rescan(config);
+            for doc in docs.values_mut().filter(|doc| !doc.evicted) {
+                doc.reindex(config, open_words);
+            }
+            let words = docs
+                .values()
+                .map(|doc| &doc.words)
+                .chain([workspace.words()])
+                .flat_map(|index| index.iter().map(|(word, _)| word))
+                .collect::<HashSet<_>>();
+            info!(
+                "Reindexed {} files and {} documents",
+                workspace.file_count(),
+                docs.len()
+            );
+            serde_json::json!({
+                "filesScanned": workspace.file_count(),
+                "wordsIndexed": words.len(),
+            })
+        }
+        ADD_TO_DICTIONARY_COMMAND => {
+            let Some(word) = params.arguments.first().and_then(serde_json::Value::as_str) else {
+                bail!("Expected the word to add");
+            };
+            let Some(path) = &config.dictionary_path else {
+                bail!("No dictionaryPath is configured");
+            };
+            dictionary::append_word(path, word)?;
+            info!("Added {word} to {}", path.display());
+            config.dictionary.add(word);
+            serde_json::Value::Null
+        }
+        command => bail!("Unknown command: {command}"),
+    };
+    Ok(Message::Response(Response::new_ok(req.id, result)))
+}
+
+/// Custom request reporting what the server holds, for tooling and debugging.
+const STATS_METHOD: &str = "lsp-word/stats";
+
+/// Custom request listing the tokens of a document or of raw text and why each is filtered,
+/// for finding out why a word is not completed.
+const TOKENIZE_METHOD: &str = "lsp-word/tokenize";
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenizeParams {
+    text_document: Option<TextDocumentIdentifier>,
+    text: Option<String>,
+    /// The language of `text`; documents use their own.
+    language_id: Option<String>,
+}
+
+/// Custom notification adding words to every completion, such as vocabulary from an
+/// external tool. They are not saved and rank after document words.
+const ADD_WORDS_METHOD: &str = "lsp-word/addWords";
+/// Custom notification dropping every word added with `lsp-word/addWords`.
+const CLEAR_WORDS_METHOD: &str = "lsp-word/clearWords";
+
+#[derive(serde::Deserialize)]
+struct AddWordsParams {
+    words: Vec<String>,
+}
+
+fn create_stats_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    workspace: &WorkspaceIndex,
+    config: &Config,
+) -> Message {
+    let indexes = docs
+        .values()
+        .map(|doc| &doc.words)
+        .chain([
+            workspace.words(),
+            &config.dictionary.words,
+            &config.tags,
+            &config.added_words,
+        ])
+        .collect::<Vec<_>>();
+    let unique = indexes
+        .iter()
+        .flat_map(|index| index.iter().map(|(word, _)| word))
+        .collect::<HashSet<_>>()
+        .len();
+    let memory = indexes
+        .iter()
+        .map(|index| index.memory_estimate())
+        .chain(docs.values().map(|doc| doc.text.len()))
+        .sum::<usize>();
+    let stats = serde_json::json!({
+        "documents": docs.len(),
+        "workspaceFiles": workspace.file_count(),
+        "uniqueWords": unique,
+        "memoryBytes": memory,
+    });
+    Message::Response(Response::new_ok(req.id, stats))
+}
+
+/// Every token the server extracts from a document or from `text`, in order, each with the
+/// reason it is never offered as a completion, if any.
+fn create_tokenize_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<TokenizeParams>(req.params)?;
+    let (text, language_id) = match (&params.text, &params.text_document) {
+        (Some(text), _) => (text.as_str(), params.language_id.as_deref().unwrap_or("")),
+        (None, Some(text_document)) => {
+            let Some(doc) = docs.get(&text_document.uri) else {
+                bail!("Unknown document: {}", text_document.uri.as_str());
+            };
+            (doc.text.as_str(), doc.language_id.as_str())
+        }
+        (None, None) => bail!("Either textDocument or text is required"),
+    };
+    let keywords = if config.exclude_keywords {
+        keywords::language_keywords(language_id)
+    } else {
+        &[]
+    };
+    let mut tokens = Vec::new();
+    for_each_document_token(text, language_id, config, |word| {
+        let dropped = dropped_reason(word, config).or_else(|| {
+            if keywords.contains(&word) {
+                Some("keyword")
+            } else if config.stop_words.contains(&word.to_lowercase()) {
+                Some("stopWord")
+            } else {
+                None
+            }
+        });
+        tokens.push(serde_json::json!({ "word": word, "dropped": dropped }));
+    });
+    let result = serde_json::json!({ "languageId": language_id, "tokens": tokens });
+    Ok(Message::Response(Response::new_ok(req.id, result)))
+}
+
+/// Quick fixes adding the words of the spelling hints overlapping the requested range to
+/// the dictionary.
+fn create_code_action_response(
+    req: Request,
+    docs: &HashMap<Uri, Document>,
+    config: &Config,
+) -> Result<Message> {
+    let params = serde_json::from_value::<CodeActionParams>(req.params)?;
+    let range = params.range;
+    let diagnostics = docs
+        .get(&params.text_document.uri)
+        .map(|doc| spelling_diagnostics(&doc.text, &doc.language_id, config))
+        .unwrap_or_default();
+    let actions: CodeActionResponse = diagnostics
+        .into_iter()
+        .filter(|diagnostic| {
+            diagnostic.range.start <= range.end && range.start <= diagnostic.range.end
+        })
+        .filter_map(|diagnostic| {
+            let word = diagnostic.data.as_ref()?.as_str()?.to_string();
+            let title = format!("Add \"{word}\" to dictionary");
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.clone(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                command: Some(Command::new(
+                    title,
+                    ADD_TO_DICTIONARY_COMMAND.to_string(),
+                    Some(vec![word.into()]),
+                )),
+                diagnostics: Some(vec![diagnostic]),
+                ..Default::default()
+            }))
+        })
+        .collect();
+    Ok(Message::Response(Response::new_ok(req.id, actions)))
+}
+
+/// The state of a running server: the open documents, the workspace index and the
+/// configuration, which includes the compiled word pattern. The workspace index is shared
+/// with the background scan, hence the lock.
+struct Backend {
+    docs: HashMap<Uri, Document>,
+    /// Words of the open documents, summed. Kept up to date by the documents themselves as
+    /// their indexes change.
+    open_words: WordIndex,
+    /// Files read from disk for completion requests about documents the client has not
+    /// opened, as some clients only send `didOpen` lazily. Dropped when the client opens them
+    /// or a file event reports a change, and read again when their modification time moved,
+    /// since nothing watches them otherwise. At most [`MAX_DISK_DOCUMENTS`] are kept.
+    disk_docs: HashMap<Uri, DiskDocument>,
+    workspace: Arc<Mutex<WorkspaceIndex>>,
+    config: Config,
+    /// When each settings problem was last shown to the user.
+    shown_problems: HashMap<String, Instant>,
+    /// Progress whose `window/workDoneProgress/create` request is still unanswered.
+    progress: Option<Progress>,
+}
+
+/// A file in [`Backend::disk_docs`], with the modification time it was read at.
+struct DiskDocument {
+    doc: Document,
+    modified: SystemTime,
+}
+
+/// Most files kept in [`Backend::disk_docs`]; the least recently used beyond are dropped.
+const MAX_DISK_DOCUMENTS: usize = 16;
+
+/// How long a settings problem that was shown is only logged when it turns up again.
+const PROBLEM_REPEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Backend {
+    fn new(config: Config, workspace: Arc<Mutex<WorkspaceIndex>>) -> Self {
+        Backend {
+            docs: HashMap::new(),
+            open_words: WordIndex::default(),
+            disk_docs: HashMap::new(),
+            workspace,
+            config,
+            shown_problems: HashMap::new(),
+            progress: None,
+        }
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<()> {
+        match not.method.as_str() {
+            notification::DidChangeTextDocument::METHOD => {
+                self.on_did_change(serde_json::from_value(not.params)?)
+            }
+            notification::DidOpenTextDocument::METHOD => {
+                self.on_did_open(serde_json::from_value(not.params)?)
+            }
+            notification::DidCloseTextDocument::METHOD => {
+                self.on_did_close(serde_json::from_value(not.params)?)
+            }
+            notification::DidSaveTextDocument::METHOD => {
+                self.on_did_save(serde_json::from_value(not.params)?)
+            }
+            notification::SetTrace::METHOD => {
+                self.on_set_trace(serde_json::from_value(not.params)?)
+            }
+            notification::DidChangeWatchedFiles::METHOD => {
+                self.on_did_change_watched_files(serde_json::from_value(not.params)?)
+            }
+            notification::DidChangeWorkspaceFolders::METHOD => {
+                self.on_did_change_workspace_folders(serde_json::from_value(not.params)?)
+            }
+            notification::DidChangeConfiguration::METHOD => {
+                self.on_did_change_configuration(serde_json::from_value(not.params)?)
+            }
+            ADD_WORDS_METHOD => {
+                let params = serde_json::from_value::<AddWordsParams>(not.params)?;
+                for word in params.words.iter().filter(|word| !word.is_empty()) {
+                    self.config.added_words.add(word, 0);
+                }
+            }
+            CLEAR_WORDS_METHOD => self.config.added_words = WordIndex::default(),
+            _ => return Ok(()),
+        }
+        // Opening, closing and saving documents, file changes and settings all change what
+        // completion finds; `on_did_change` handles edits itself.
+        if ![
+            notification::DidChangeTextDocument::METHOD,
+            notification::SetTrace::METHOD,
+        ]
+        .contains(&not.method.as_str())
+        {
+            self.forget_candidates(None);
+        }
+        Ok(())
+    }
+
+    /// Drops the completion candidates of every document but `keep`.
+    fn forget_candidates(&mut self, keep: Option<&Uri>) {
+        for (uri, doc) in &mut self.docs {
+            if Some(uri) != keep {
+                doc.candidates = None;
+            }
+        }
+    }
+
+    fn on_did_change(&mut self, params: lsp_types::DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        if params.content_changes.is_empty() {
+            warn!(
+                "Ignoring a change without content changes for {}",
+                uri.as_str()
+            );
+            return;
+        }
+        let doc = self
+            .docs
+            .entry(uri.clone())
+            .or_insert_with(|| Document::new(String::new(), String::new(), &self.config));
+        for change in params.content_changes {
+            doc.apply_change(change, &self.config, &mut self.open_words);
+        }
+        // Checked again with the rebuild, or after the same quiet period when the edits were
+        // indexed in place, rather than on every keystroke.
+        if self.config.publishes_diagnostics() {
+            doc.diagnostics_at = doc
+                .reindex_at
+                .or(doc.diagnostics_at)
+                .or_else(|| Some(Instant::now() + REINDEX_DEBOUNCE));
+        }
+        // The edited document decides for itself whether its candidates still hold.
+        self.forget_candidates(Some(&uri));
+        self.focus(&uri);
+    }
+
+    fn on_did_open(&mut self, params: lsp_types::DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.disk_docs.remove(&uri);
+        let doc = Document::new(
+            params.text_document.text,
+            params.text_document.language_id,
+            &self.config,
+        );
+        doc.open(&mut self.open_words);
+        if let Some(previous) = self.docs.insert(uri.clone(), doc) {
+            previous.close(&mut self.open_words);
+        }
+        self.focus(&uri);
+    }
+
+    fn on_did_close(&mut self, params: lsp_types::DidCloseTextDocumentParams) {
+        if let Some(doc) = self.docs.remove(&params.text_document.uri) {
+            doc.close(&mut self.open_words);
+        }
+    }
+
+    fn on_did_save(&mut self, params: lsp_types::DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+        // A save of a document that is not open says nothing the file watcher would not.
+        if !self.docs.contains_key(&uri) {
+            return;
+        }
+        // Without the text, the file on disk is the source of truth (e.g. after a formatter ran).
+        let text = match params.text {
+            Some(text) => Some(text),
+            None => workspace::uri_to_path(&uri).and_then(|path| fs::read_to_string(path).ok()),
+        };
+        if let Some(text) = text
+            && let Some(doc) = self.docs.get_mut(&uri)
+        {
+            doc.text = text;
+            doc.reindex(&self.config, &mut self.open_words);
+            self.focus(&uri);
+        }
+    }
+
+    fn on_set_trace(&mut self, params: SetTraceParams) {
+        self.config.trace = params.value;
+    }
+
+    fn on_did_change_watched_files(&mut self, params: lsp_types::DidChangeWatchedFilesParams) {
+        let mut workspace = self.workspace.lock().unwrap();
+        // Created, changed and deleted files alike are re-read; a deleted one reads as empty.
+        for change in &params.changes {
+            self.disk_docs.remove(&change.uri);
+            if let Some(path) = workspace::uri_to_path(&change.uri) {
+                workspace.update_file(&path, &self.config);
+            }
+        }
+    }
+
+    fn on_did_change_workspace_folders(
+        &mut self,
+        params: lsp_types::DidChangeWorkspaceFoldersParams,
+    ) {
+        let paths = |folders: &[lsp_types::WorkspaceFolder]| {
+            folders
+                .iter()
+                .filter_map(|folder| workspace::uri_to_path(&folder.uri))
+                .collect::<Vec<_>>()
+        };
+        self.workspace.lock().unwrap().change_folders(
+            &paths(&params.event.added),
+            &paths(&params.event.removed),
+            &self.config,
+        );
+    }
+
+    fn on_did_change_configuration(&mut self, params: lsp_types::DidChangeConfigurationParams) {
+        // Clients either send our settings directly or nest them under the server name.
+        let settings = match params.settings {
+            serde_json::Value::Object(mut map) if map.contains_key("lsp-word") => {
+                map.remove("lsp-word").unwrap_or_default()
+            }
+            settings => settings,
+        };
+        self.apply_settings(settings);
+    }
+
+    /// Applies settings received after startup and re-reads what they affect: the open
+    /// documents when words are split differently, and the workspace, in the background, when
+    /// its files are too. Returns the handle of that rescan, if one was started.
+    fn apply_settings(&mut self, settings: serde_json::Value) -> Option<JoinHandle<()>> {
+        let (tokenization, scan_settings) =
+            (self.config.tokenization(), self.config.scan_settings());
+        self.config.update(settings);
+        let retokenized = self.config.tokenization() != tokenization;
+        if retokenized {
+            for doc in self.docs.values_mut().filter(|doc| !doc.evicted) {
+                doc.reindex(&self.config, &mut self.open_words);
+            }
+        }
+        (retokenized || self.config.scan_settings() != scan_settings)
+            .then(|| WorkspaceIndex::rescan_in_background(&self.workspace, &self.config, None))
+    }
+
+    /// Answers `req`, or returns `None` for methods the server does not implement.
+    fn handle_request(&mut self, req: Request) -> Result<Option<Message>> {
+        let from_disk = request_document(&req)
+            .filter(|_| req.method == request::Completion::METHOD)
+            .and_then(|uri| Some((self.borrow_from_disk(&uri)?, uri)));
+        // Borrowed documents are answered like open ones, without taking part in eviction.
+        if let Some(uri) = request_document(&req).filter(|_| from_disk.is_none()) {
+            self.focus(&uri);
+        }
+        let response = self.answer(req);
+        if let Some((modified, uri)) = from_disk
+            && let Some(doc) = self.docs.remove(&uri)
+        {
+            self.disk_docs.insert(uri, DiskDocument { doc, modified });
+            if self.disk_docs.len() > MAX_DISK_DOCUMENTS
+                && let Some(oldest) = self
+                    .disk_docs
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.doc.last_used)
+                    .map(|(uri, _)| uri.clone())
+            {
+                self.disk_docs.remove(&oldest);
+            }
+        }
+        response
+    }
+
+    /// [`Backend::handle_request`] once the document it is about is in `docs`, if it can be.
+    fn answer(&mut self, req: Request) -> Result<Option<Message>> {
+        let docs = &mut self.docs;
+        let open_words = &mut self.open_words;
+        let config = &mut self.config;
+        let workspace = &mut self.workspace.lock().unwrap();
+        let response = match req.method.as_str() {
+            request::Completion::METHOD => {
+                create_completion_response(req, docs, workspace, open_words, config)?
+            }
+            request::ResolveCompletionItem::METHOD => {
+                create_completion_resolve_response(req, docs, config)?
+            }
+            request::HoverRequest::METHOD => create_hover_response(req, docs, config)?,
+            request::DocumentHighlightRequest::METHOD => {
+                create_document_highlight_response(req, docs, config)?
+            }
+            request::References::METHOD => create_references_response(req, docs, config)?,
+            request::Rename::METHOD => create_rename_response(req, docs, config)?,
+            request::DocumentSymbolRequest::METHOD => {
+                create_document_symbol_response(req, docs, config)?
+            }
+            request::WorkspaceSymbolRequest::METHOD => {
+                create_workspace_symbol_response(req, docs, workspace, config)?
+            }
+            request::SelectionRangeRequest::METHOD => {
+                create_selection_range_response(req, docs, config)?
+            }
+            request::FoldingRangeRequest::METHOD => {
+                create_folding_range_response(req, docs, config)?
+            }
+            request::CodeActionRequest::METHOD => create_code_action_response(req, docs, config)?,
+            request::WillSaveWaitUntil::METHOD => {
+                create_will_save_wait_until_response(req, docs, config)?
+            }
+            STATS_METHOD => create_stats_response(req, docs, workspace, config),
+            TOKENIZE_METHOD => create_tokenize_response(req, docs, config)?,
+            request::ExecuteCommand::METHOD => {
+                create_execute_command_response(req, docs, workspace, open_words, config)?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(response))
+    }
+
+    fn focus(&mut self, uri: &Uri) {
+        focus(&mut self.docs, &mut self.open_words, uri, &self.config);
+    }
+
+    /// Moves the file behind an unopened `uri` from `disk_docs` into `docs` for one request,
+    /// reading it first unless it is cached and unmodified, and returns the modification time
+    /// it was read at if it did. Its language is guessed from the extension. Its words stay
+    /// out of `open_words`, since the client does not have it open.
+    fn borrow_from_disk(&mut self, uri: &Uri) -> Option<SystemTime> {
+        if self.docs.contains_key(uri) {
+            return None;
+        }
+        let path = workspace::uri_to_path(uri)?;
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+        let mut doc = match self.disk_docs.remove(uri) {
+            Some(cached) if cached.modified == modified => cached.doc,
+            _ => {
+                let text = workspace::read_text_file(&path, self.config.max_file_size_bytes)?;
+                let language_id = workspace::language_id(&path).to_string();
+                Document::new(text, language_id, &self.config)
+            }
+        };
+        doc.last_used = tick();
+        self.docs.insert(uri.clone(), doc);
+        Some(modified)
+    }
+
+    fn next_reindex(&self) -> Option<Instant> {
+        next_reindex(&self.docs)
+    }
+
+    fn reindex_due(&mut self, now: Instant) -> usize {
+        reindex_due(&mut self.docs, &mut self.open_words, &self.config, now)
+    }
+
+    fn save_word_cache(&self, path: &Path) {
+        let counts = session_word_counts(&self.docs, &self.workspace.lock().unwrap());
+        if let Err(e) = cache::save_word_cache(path, counts) {
+            warn!("Failed to save word cache {}: {e}", path.display());
+        }
+    }
+
+    /// Requests to send once the client reports that it is initialized: dynamic
+    /// registrations, for the capabilities the client can register that way, and a pull of
+    /// the settings, which apply over `initializationOptions`.
+    fn initialized_requests(&self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        if self.config.watch_files_support {
+            requests.push(watch_files_registration());
+        }
+        if self.config.configuration_support {
+            requests.push(configuration_request());
+        }
+        requests
+    }
+
+    /// Handles the client's answers to requests the server sent, returning whether they
+    /// changed the settings. Only the settings pulled with `workspace/configuration` and the
+    /// created progress token matter; a client that never answers leaves the
+    /// `initializationOptions` in place, and the workspace scan unannounced.
+    fn handle_response(&mut self, resp: Response) -> bool {
+        if self
+            .progress
+            .as_ref()
+            .is_some_and(|progress| progress.created(&resp))
+        {
+            self.progress = None;
+            return false;
+        }
+        if resp.id != CONFIGURATION_REQUEST_ID.to_string().into() {
+            return false;
+        }
+        let result = match resp.response_result {
+            Ok(result) => result,
+            Err(error) => {
+                warn!("Could not pull the settings: {}", error.message);
+                return false;
+            }
+        };
+        let settings = serde_json::from_value::<Vec<serde_json::Value>>(result)
+            .ok()
+            .and_then(|items| items.into_iter().next())
+            .unwrap_or_default();
+        self.apply_settings(settings);
+        true
+    }
+
+    /// `window/showMessage` warnings for the settings problems found since the last call,
+    /// leaving out those shown less than `PROBLEM_REPEAT_INTERVAL` ago so that the same bad
+    /// settings sent again do not keep raising popups.
+    fn problem_notifications(&mut self, now: Instant) -> Vec<Notification> {
+        self.shown_problems
+            .retain(|_, shown| now.duration_since(*shown) < PROBLEM_REPEAT_INTERVAL);
+        let mut notifications = Vec::new();
+        for problem in std::mem::take(&mut self.config.problems) {
+            if self.shown_problems.contains_key(&problem) {
+                continue;
+            }
+            notifications.push(Notification::new(
+                notification::ShowMessage::METHOD.to_string(),
+                ShowMessageParams {
+                    typ: MessageType::WARNING,
+                    message: format!("lsp-word: {problem}"),
+                },
+            ));
+            self.shown_problems.insert(problem, now);
+        }
+        notifications
+    }
+
+    fn diagnostic_notifications(
+        &self,
+        method: &str,
+        uri: Option<Uri>,
+        was_checking: bool,
+    ) -> Vec<Notification> {
+        diagnostic_notifications(method, uri, &self.docs, &self.config, was_checking)
+    }
+
+    /// Diagnostics of the edited documents whose refresh is due by `now`, to be called after
+    /// [`Backend::reindex_due`].
+    fn due_diagnostics(&mut self, now: Instant) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+        for (uri, doc) in &mut self.docs {
+            if doc.diagnostics_at.is_some_and(|at| at <= now) {
+                doc.diagnostics_at = None;
+                if self.config.publishes_diagnostics() {
+                    let diagnostics = document_diagnostics(doc, &self.config);
+                    notifications.push(publish_diagnostics(uri, diagnostics));
+                }
+            }
+        }
+        notifications
+    }
+}
+
+/// Where word frequencies are kept between sessions: next to the log in `log_dir`, the
+/// directory it was opened in, or without a log file in the preferred data directory.
+fn word_cache_path(log_dir: Option<&Path>) -> Option<PathBuf> {
+    let dir = match log_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => data_dirs(None).into_iter().next()?,
+    };
+    Some(dir.join("words.json"))
+}
+
+/// Word frequencies of this session, falling back to the cached ones for words not seen in it.
+/// A workspace file that is open counts once, with the words of the open document.
+fn session_word_counts(
+    docs: &HashMap<Uri, Document>,
+    workspace: &WorkspaceIndex,
+) -> HashMap<String, usize> {
+    let mut live = HashMap::new();
+    // Evicted documents have no words to stand in for their file.
+    let open = docs
+        .iter()
+        .filter(|(_, doc)| !doc.evicted)
+        .filter_map(|(uri, _)| workspace::uri_to_path(uri))
+        .collect::<HashSet<_>>();
+    for (word, count) in docs.values().flat_map(|doc| doc.words.iter()) {
+        *live.entry(word.to_owned()).or_insert(0) += count;
+    }
+    let files = workspace.files().filter(|(path, _)| !open.contains(*path));
+    for (word, count) in files.flat_map(|(_, words)| words) {
+        *live.entry(word.to_string()).or_insert(0) += count;
+    }
+    let mut counts = workspace
+        .cached_words()
+        .iter()
+        .map(|(word, count)| (word.to_owned(), count))
+        .collect::<HashMap<_, _>>();
+    counts.extend(live);
+    counts
+}
+
+/// The document a request is about, for the requests that name one.
+fn request_document(req: &Request) -> Option<Uri> {
+    document_uri(&req.params)
+}
+
+fn document_uri(params: &serde_json::Value) -> Option<Uri> {
+    params
+        .pointer("/textDocument/uri")
+        .and_then(serde_json::Value::as_str)
+        .and_then(|uri| uri.parse().ok())
+}
+
+/// A `$/logTrace` notification about a request handled since `started`, unless tracing is off.
+fn log_trace(
+    trace: TraceValue,
+    method: &str,
+    id: &RequestId,
+    response: Option<&Message>,
+    started: Instant,
+) -> Option<Notification> {
+    if trace == TraceValue::Off {
+        return None;
+    }
+    let elapsed = started.elapsed();
+    let outcome = match response {
+        Some(Message::Response(Response {
+            response_result: Err(error),
+            ..
+        })) => format!("failed: {}", error.message),
+        Some(_) => "answered".to_string(),
+        None => "not handled".to_string(),
+    };
+    let params = LogTraceParams {
+        message: format!("Handled {method} ({id}) in {}ms", elapsed.as_millis()),
+        verbose: (trace == TraceValue::Verbose)
+            .then(|| format!("Request {id} {outcome} after {elapsed:?}")),
+    };
+    Some(Notification::new(
+        notification::LogTrace::METHOD.to_string(),
+        params,
+    ))
+}
+
+/// Removes `$/cancelRequest` notifications from `queue`, returning the ids they cancel
+/// among the requests still waiting in it. Cancellations of answered requests are dropped.
+fn take_cancellations(queue: &mut VecDeque<Message>) -> HashSet<RequestId> {
+    let mut ids = HashSet::new();
+    queue.retain(|msg| match msg {
+        Message::Notification(not) if not.method == notification::Cancel::METHOD => {
+            if let Ok(params) = serde_json::from_value::<CancelParams>(not.params.clone()) {
+                ids.insert(match params.id {
+                    NumberOrString::Number(id) => RequestId::from(id),
+                    NumberOrString::String(id) => RequestId::from(id),
+                });
+            }
+            false
+        }
+        _ => true,
+    });
+    ids.retain(|id| {
+        queue
+            .iter()
+            .any(|msg| matches!(msg, Message::Request(req) if req.id == *id))
+    });
+    ids
+}
+
+/// Ids of the requests in `queue` about a document that a change later in the queue edits,
+/// whose results would be stale by the time they are read.
+fn superseded_requests(queue: &VecDeque<Message>) -> HashSet<RequestId> {
+    let document =
+        |params: &serde_json::Value| params["textDocument"]["uri"].as_str().map(str::to_owned);
+    let mut ids = HashSet::new();
+    for (i, msg) in queue.iter().enumerate() {
+        let Message::Request(req) = msg else {
+            continue;
+        };
+        let Some(uri) = document(&req.params) else {
+            continue;
+        };
+        let edited = queue.iter().skip(i + 1).any(|later| {
+            matches!(later, Message::Notification(not)
+                if not.method == notification::DidChangeTextDocument::METHOD
+                    && document(&not.params).as_ref() == Some(&uri))
+        });
+        if edited {
+            ids.insert(req.id.clone());
+        }
+    }
+    ids
+}
+
+/// Serves requests until `exit`. With a `cache_path`, word frequencies are saved there on shutdown.
+fn serve(
+    connection: Connection,
+    config: Config,
+    workspace: Arc<Mutex<WorkspaceIndex>>,
+    cache_path: Option<PathBuf>,
+    progress: Option<Progress>,
+) -> Result<()> {
+    let mut backend = Backend::new(config, workspace);
+    backend.progress = progress;
+    // Problems with the initialization options, now that the client may be told.
+    for not in backend.problem_notifications(Instant::now()) {
+        connection.sender.send(not.into())?;
+    }
+    let mut queue = VecDeque::new();
+    let mut cancelled = HashSet::new();
+    let mut modified = HashSet::new();
+    let mut shutdown_requested = false;
+    loop {
+        // Also while messages keep arriving, so that a steady stream of them cannot hold
+        // the rebuilds back.
+        if backend
+            .next_reindex()
+            .is_some_and(|at| at <= Instant::now())
+        {
+            let now = Instant::now();
+            backend.reindex_due(now);
+            for not in backend.due_diagnostics(now) {
+                connection.sender.send(not.into())?;
+            }
+        }
+        if queue.is_empty() {
+            let msg = match backend.next_reindex() {
+                Some(deadline) => match connection.receiver.recv_deadline(deadline) {
+                    Ok(msg) => msg,
+                    Err(e) if e.is_timeout() => continue,
+                    Err(_) => break,
+                },
+                None => match connection.receiver.recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                },
+            };
+            queue.push_back(msg);
+        }
+        // Look at everything that already arrived, so that requests cancelled meanwhile are skipped.
+        queue.extend(connection.receiver.try_iter());
+        cancelled.extend(take_cancellations(&mut queue));
+        modified.extend(superseded_requests(&queue));
+        let Some(msg) = queue.pop_front() else {
+            continue;
+        };
+        match msg {
+            Message::Request(req) => {
+                // Not `Connection::handle_shutdown`: it waits on the channel for `exit`,
+                // which may already have been moved into the queue.
+                if req.method == request::Shutdown::METHOD {
+                    shutdown_requested = true;
+                    if let Some(path) = &cache_path {
+                        backend.save_word_cache(path);
+                    }
+                    connection
+                        .sender
+                        .send(Response::new_ok(req.id, ()).into())?;
+                    continue;
+                }
+                if shutdown_requested {
+                    connection.sender.send(
+                        Response::new_err(
+                            req.id,
+                            ErrorCode::InvalidRequest as i32,
+                            "The server is shutting down".to_string(),
+                        )
+                        .into(),
+                    )?;
+                    continue;
+                }
+                if cancelled.remove(&req.id) {
+                    connection.sender.send(
+                        Response::new_err(
+                            req.id,
+                            ErrorCode::RequestCanceled as i32,
+                            "Request cancelled".to_string(),
+                        )
+                        .into(),
+                    )?;
+                    continue;
+                }
+                if modified.remove(&req.id) {
+                    connection.sender.send(
+                        Response::new_err(
+                            req.id,
+                            ErrorCode::ContentModified as i32,
+                            "Content modified".to_string(),
+                        )
+                        .into(),
+                    )?;
+                    continue;
+                }
+                let id = req.id.clone();
+                let method = req.method.clone();
+                let was_checking = backend.config.publishes_diagnostics();
+                let started = Instant::now();
+                // A failing request gets an error response; it must not take the server down.
+                let response = backend.handle_request(req).unwrap_or_else(|e| {
+                    error!("{e:?}");
+                    Some(Message::Response(Response::new_err(
+                        id.clone(),
+                        ErrorCode::InvalidParams as i32,
+                        e.to_string(),
+                    )))
+                });
+                let trace = log_trace(
+                    backend.config.trace,
+                    &method,
+                    &id,
+                    response.as_ref(),
+                    started,
+                );
+                if let Some(response) = response {
+                    connection.sender.send(response)?;
+                }
+                if let Some(trace) = trace {
+                    connection.sender.send(trace.into())?;
+                }
+                for not in backend.diagnostic_notifications(&method, None, was_checking) {
+                    connection.sender.send(not.into())?;
+                }
+            }
+            Message::Notification(not) => match not.method.as_str() {
+                notification::Exit::METHOD if shutdown_requested => return Ok(()),
+                notification::Exit::METHOD => bail!("Received exit before shutdown"),
+                notification::Initialized::METHOD => {
+                    // The client's answers are responses that the loop ignores.
+                    for req in backend.initialized_requests() {
+                        connection.sender.send(req.into())?;
+                    }
+                }
+                _ => {
+                    let method = not.method.clone();
+                    let uri = document_uri(&not.params);
+                    let was_checking = backend.config.publishes_diagnostics();
+                    if let Err(e) = backend.handle_notification(not) {
+                        error!("{e:?}");
+                    }
+                    for not in backend.diagnostic_notifications(&method, uri, was_checking) {
+                        connection.sender.send(not.into())?;
+                    }
+                    for not in backend.problem_notifications(Instant::now()) {
+                        connection.sender.send(not.into())?;
+                    }
+                }
+            },
+            Message::Response(resp) => {
+                let was_checking = backend.config.publishes_diagnostics();
+                if backend.handle_response(resp) {
+                    let method = notification::DidChangeConfiguration::METHOD;
+                    for not in backend.diagnostic_notifications(method, None, was_checking) {
+                        connection.sender.send(not.into())?;
+                    }
+                    for not in backend.problem_notifications(Instant::now()) {
+                        connection.sender.send(not.into())?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn server_capabilities(config: &Config) -> ServerCapabilities {
+    ServerCapabilities {
+        position_encoding: Some(config.position_encoding.kind()),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(config.trigger_characters.clone()),
+            resolve_provider: Some(true),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            }),
+            file_operations: None,
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![
+                REINDEX_COMMAND.to_string(),
+                ADD_TO_DICTIONARY_COMMAND.to_string(),
+            ],
+            ..Default::default()
+        }),
+        // Capabilities are sent before anything is indexed, so the counts are left to the
+        // stats request, which reports them live.
+        experimental: Some(serde_json::json!({
+            "lspWord": {
+                "version": 1,
+                "statsRequest": STATS_METHOD,
+            }
+        })),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::INCREMENTAL),
+                save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                    include_text: Some(true),
+                })),
+                // Always asked, so that `trimTrailingWhitespaceOnSave` can be turned on later.
+                will_save_wait_until: Some(true),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    }
+}
+
+fn initialize_result(config: &Config) -> InitializeResult {
+    InitializeResult {
+        capabilities: server_capabilities(config),
+        server_info: Some(ServerInfo {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }),
+    }
+}
+
+/// Asks the client to report changes to files on disk, so that the workspace index follows
+/// edits made outside the editor.
+fn watch_files_registration() -> Request {
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![FileSystemWatcher {
+            glob_pattern: GlobPattern::String("**/*".to_string()),
+            kind: None,
+        }],
+    };
+    Request::new(
+        "lsp-word/watch-files".to_string().into(),
+        request::RegisterCapability::METHOD.to_string(),
+        RegistrationParams {
+            registrations: vec![Registration {
+                id: "lsp-word/watch-files".to_string(),
+                method: notification::DidChangeWatchedFiles::METHOD.to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            }],
+        },
+    )
+}
+
+const CONFIGURATION_REQUEST_ID: &str = "lsp-word/configuration";
+
+fn configuration_request() -> Request {
+    Request::new(
+        CONFIGURATION_REQUEST_ID.to_string().into(),
+        request::WorkspaceConfiguration::METHOD.to_string(),
+        lsp_types::ConfigurationParams {
+            items: vec![lsp_types::ConfigurationItem {
+                scope_uri: None,
+                section: Some("lsp-word".to_string()),
+            }],
+        },
+    )
+}
+
+/// Folders to index: every workspace folder, or the deprecated `rootUri` for clients that
+/// send no folders.
+fn workspace_roots(params: &InitializeParams) -> Vec<PathBuf> {
+    match &params.workspace_folders {
+        Some(folders) if !folders.is_empty() => folders
+            .iter()
+            .filter_map(|folder| workspace::uri_to_path(&folder.uri))
+            .collect(),
+        #[allow(deprecated)]
+        _ => params
+            .root_uri
+            .as_ref()
+            .and_then(workspace::uri_to_path)
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn main() -> Result<()> {
+    let mut logger = init_logger();
+    info!("Starting LSP server");
+    let (connection, io_threads) = Connection::stdio();
+
+    let (id, params) = match connection.initialize_start() {
+        Ok(start) => start,
+        Err(err) => {
+            error!("Error initializing connection: {:?}", err);
+            return Ok(());
+        }
+    };
+    info!("Initialized with params: {}", params);
+    let params = serde_json::from_value::<InitializeParams>(params)?;
+    let mut config = Config::from_value(params.initialization_options.clone());
+    let completion = params
+        .capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.completion.as_ref());
+    config.completion_item_defaults = completion
+        .and_then(|completion| completion.completion_list.as_ref())
+        .and_then(|list| list.item_defaults.clone())
+        .unwrap_or_default();
+    config.trace = params.trace.unwrap_or_default();
+    config.position_encoding = Encoding::negotiate(
+        params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref())
+            .unwrap_or_default(),
+    );
+    config.commit_characters_support = completion
+        .and_then(|completion| completion.completion_item.as_ref())
+        .and_then(|item| item.commit_characters_support)
+        .unwrap_or(false);
+    config.configuration_support = params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.configuration)
+        .unwrap_or(false);
+    config.watch_files_support = params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .and_then(|watched| watched.dynamic_registration)
+        .unwrap_or(false);
+    if let Err(e) = logger.filter.reload(log_filter(config.log_level)) {
+        error!("Failed to apply logLevel: {e}");
+    }
+    // Without a writable `logDir`, logging carries on where it started.
+    if let Some(dir) = &config.log_dir {
+        match create_log_file(dir, max_log_size()) {
+            Ok(log_file) => match logger.output.reload(log_output(Some(log_file))) {
+                Ok(()) => logger.dir = Some(dir.clone()),
+                Err(e) => error!("Failed to apply logDir: {e}"),
+            },
+            Err(e) => warn!("Cannot log to {}: {e}", dir.display()),
+        }
+    }
+    // Capabilities depend on the configuration, so they are only known once the params are read.
+    // Not `Connection::initialize_finish`, which would consume the `initialized` notification
+    // that `serve` registers capabilities on.
+    connection
+        .sender
+        .send(Response::new_ok(id, initialize_result(&config)).into())?;
+    let roots = workspace_roots(&params);
+    let progress = params
+        .capabilities
+        .window
+        .and_then(|window| window.work_done_progress)
+        .unwrap_or(false)
+        .then(|| {
+            Progress::begin(
+                connection.sender.clone(),
+                "lsp-word/scan",
+                "Indexing workspace",
+            )
+        });
+    // Completion uses whatever has been read so far while the workspace is still being scanned.
+    let (workspace, _) = WorkspaceIndex::scan_in_background(&roots, &config, progress.clone());
+    // The cache follows the log, so an unwritable `logDir` does not lose it.
+    let cache_path = word_cache_path(logger.dir.as_deref());
+    if let Some(path) = &cache_path {
+        workspace
+            .lock()
+            .unwrap()
+            .set_cached_words(cache::load_word_cache(path));
+    }
+    let result = serve(connection, config, workspace, cache_path, progress);
+    if let Err(e) = &result {
+        error!("{e:?}");
+    }
+    io_threads.join()?;
+    // An error makes the process exit with a nonzero status, as the protocol asks for
+    // an exit without a prior shutdown.
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::TextDocumentPositionParams;
+
+    fn doc(text: &str) -> Document {
+        Document::new(text.to_string(), String::new(), &Config::default())
+    }
+
+    #[test]
+    fn test_create_log_file() {
+        let temp_dir = std::env::temp_dir();
+        let file = create_log_file(&temp_dir.join("lsp-word"), DEFAULT_MAX_LOG_SIZE).unwrap();
+        assert!(file.metadata().unwrap().is_file());
+        let log_path = temp_dir.join("lsp-word").join("lsp-word.log");
+        assert!(log_path.exists());
+    }
+
+    #[test]
+    fn test_log_dir_is_preferred() {
+        let base = std::env::temp_dir().join("lsp-word-test-log-dir");
+        let _ = fs::remove_dir_all(&base);
+        let dir = base.join("missing").join("logs");
+        let (chosen, file) = open_log_file(&data_dirs(Some(&dir)), DEFAULT_MAX_LOG_SIZE).unwrap();
+        assert_eq!(chosen, dir);
+        assert!(file.metadata().unwrap().is_file());
+        assert!(dir.join("lsp-word.log").exists());
+        assert_eq!(word_cache_path(Some(&dir)), Some(dir.join("words.json")));
+
+        // A directory that cannot be created is passed over.
+        fs::write(base.join("file"), "").unwrap();
+        let blocked = base.join("file").join("logs");
+        let dirs = [blocked.clone(), dir.clone()];
+        assert_eq!(
+            open_log_file(&dirs, DEFAULT_MAX_LOG_SIZE).map(|(chosen, _)| chosen),
+            Some(dir)
+        );
+    }
+
+    #[test]
+    fn test_create_log_file_rotates_large_logs() {
+        let base = std::env::temp_dir().join("lsp-word-test-log-rotation");
+        let _ = fs::remove_dir_all(&base);
+        let dir = base.join("lsp-word");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lsp-word.log"), "x".repeat(32)).unwrap();
+        fs::write(dir.join("lsp-word.log.1"), "older").unwrap();
+
+        let file = create_log_file(&dir, 16).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 0);
+        assert_eq!(
+            fs::read_to_string(dir.join("lsp-word.log.1")).unwrap(),
+            "x".repeat(32)
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("lsp-word.log.2")).unwrap(),
+            "older"
+        );
+
+        // Below the limit the log is appended to as before.
+        drop(file);
+        fs::write(dir.join("lsp-word.log"), "small").unwrap();
+        create_log_file(&dir, 16).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("lsp-word.log")).unwrap(),
+            "small"
+        );
+    }
+
+    #[test]
+    fn test_log_filter_level() {
+        assert_eq!(
+            log_filter(Level::DEBUG).max_level_hint(),
+            Some(LevelFilter::DEBUG)
+        );
+        let config = Config::from_value(Some(serde_json::json!({ "logLevel": "debug" })));
+        assert_eq!(
+            log_filter(config.log_level).max_level_hint(),
+            Some(LevelFilter::DEBUG)
+        );
+    }
+
+    #[test]
+    fn test_load_all_words_basic() {
+        let words: HashSet<String> =
+            load_all_words("fn main() { let test = 1; }", &Config::default())
+                .into_keys()
+                .collect();
+        let expected_words: HashSet<String> = ["fn", "main", "let", "test"]
+            .iter()
+            .cloned()
+            .map(String::from)
+            .collect();
+
+        assert_eq!(words, expected_words);
+    }
+
+    #[test]
+    fn test_load_all_words_empty() {
+        let words: HashSet<String> = load_all_words("", &Config::default()).into_keys().collect();
+        assert!(words.is_empty());
+    }
+
+    #[test]
+    fn test_load_all_words_special_chars() {
+        let words: HashSet<String> = load_all_words("let x1 = 42; // @#$%", &Config::default())
+            .into_keys()
+            .collect();
+        let expected_words: HashSet<String> =
+            ["let", "x1"].iter().cloned().map(String::from).collect();
+
+        assert_eq!(words, expected_words);
+    }
+
+    #[test]
+    fn test_create_completion_response() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("fn main() { let test = 1; }"));
+
+        let req = Request {
+            id: 1.into(),
+            method: "textDocument/completion".to_string(),
+            params: serde_json::to_value(CompletionParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: lsp_types::Position {
+                        line: 0,
+                        character: 0,
+                    },
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+            .unwrap(),
+        };
+
+        let response = create_completion_response(
+            req,
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        if let Message::Response(resp) = response {
+            assert!(resp.response_result.is_ok());
+        } else {
+            panic!("Expected a response message");
+        }
+    }
+
+    fn completion_request(uri: &Uri, line: u32, character: u32) -> Request {
+        Request {
+            id: 1.into(),
+            method: "textDocument/completion".to_string(),
+            params: serde_json::to_value(CompletionParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: lsp_types::Position { line, character },
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+            .unwrap(),
+        }
+    }
+
+    fn triggered_completion_request(
+        uri: &Uri,
+        line: u32,
+        character: u32,
+        trigger_kind: CompletionTriggerKind,
+    ) -> Request {
+        let mut req = completion_request(uri, line, character);
+        req.params["context"] = serde_json::to_value(lsp_types::CompletionContext {
+            trigger_kind,
+            trigger_character: None,
+        })
+        .unwrap();
+        req
+    }
+
+    fn completion_list(response: Message) -> CompletionList {
+        let Message::Response(resp) = response else {
+            panic!("Expected a response message");
+        };
+        let Some(CompletionResponse::List(list)) =
+            serde_json::from_value(resp.response_result.unwrap()).unwrap()
+        else {
+            panic!("Expected a completion list");
+        };
+        list
+    }
+
+    fn completion_items(response: Message) -> Vec<CompletionItem> {
+        completion_list(response).items
+    }
+
+    fn completion_labels(response: Message) -> HashSet<String> {
+        completion_items(response)
+            .into_iter()
+            .map(|item| item.label)
+            .collect()
+    }
+
+    #[test]
+    fn test_word_prefix() {
+        let content = "let foo = 1;\nfoo.bar";
+        assert_eq!(
+            word_prefix(
+                content,
+                Position::new(0, 6),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "fo"
+        );
+        assert_eq!(
+            word_prefix(
+                content,
+                Position::new(1, 0),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            ""
+        );
+        assert_eq!(
+            word_prefix(
+                content,
+                Position::new(1, 4),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_completion_filters_by_prefix_mid_line() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("let test = 1; let tea = te; tame"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 0, 26),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let items = completion_items(response);
+        // The prefix stops at the cursor, so `; tame` after it does not narrow the matches.
+        let labels: HashSet<String> = items.iter().map(|item| item.label.clone()).collect();
+        let expected: HashSet<String> = ["test", "tea"].map(String::from).into();
+        assert_eq!(labels, expected);
+        // Only the prefix is replaced, never the text after the cursor.
+        let prefix = Range::new(Position::new(0, 24), Position::new(0, 26));
+        for item in items {
+            assert_eq!(
+                item.text_edit,
+                Some(CompletionTextEdit::Edit(TextEdit::new(prefix, item.label)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_completion_at_column_zero_returns_all() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("fn main() { let test = 1; }"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 0, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["fn", "main", "let", "test"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_completion_after_dot_returns_all() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("foo.bar\nfoo."));
+
+        let response = create_completion_response(
+            completion_request(&uri, 1, 4),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["foo", "bar"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_completion_ranks_by_frequency() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("once common common common"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 0, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let items = completion_items(response);
+        let sort_text = |label: &str| {
+            items
+                .iter()
+                .find(|item| item.label == label)
+                .and_then(|item| item.sort_text.clone())
+                .unwrap()
+        };
+        assert_eq!(sort_text("common"), "0000");
+        assert!(sort_text("common") < sort_text("once"));
+    }
+
+    #[test]
+    fn test_completion_ranks_nearby_words_first() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let other = "file:///other".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("far\n\n\n\nnear\nn\nclose"));
+        // Frequent elsewhere, but not in the requesting document at all.
+        docs.insert(other, doc("nowhere nowhere nowhere far far far"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 5, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let labels: Vec<String> = completion_items(response)
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, ["close", "near", "far", "nowhere"]);
+    }
+
+    #[test]
+    fn test_line_distances() {
+        let distances = line_distances(
+            "alpha\nbeta alpha\ngamma",
+            "",
+            Position::new(2, 2),
+            &Config::default(),
+        );
+        assert_eq!(distances["alpha"], 1);
+        assert_eq!(distances["beta"], 1);
+        // The word under the cursor is not counted as its own occurrence.
+        assert_eq!(distances.get("gamma"), None);
+    }
+
+    #[test]
+    fn test_completion_includes_words_from_other_documents() {
+        let first = "file:///first".parse::<Uri>().unwrap();
+        let second = "file:///second".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(first.clone(), doc("let shared = 1;"));
+        docs.insert(second.clone(), doc("let shared = unique;"));
+
+        let response = create_completion_response(
+            completion_request(&first, 0, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let labels = completion_labels(response);
+        assert!(labels.contains("unique"));
+        assert!(labels.contains("shared"));
+    }
+
+    #[test]
+    fn test_completion_for_unknown_document_with_all_documents() {
+        let known = "file:///known".parse::<Uri>().unwrap();
+        let unknown = "file:///unknown".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(known, doc("let known = 1;"));
+
+        let response = create_completion_response(
+            completion_request(&unknown, 0, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(completion_labels(response).contains("known"));
+    }
+
+    #[test]
+    fn test_did_close_removes_document() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        let open = Notification::new(
+            notification::DidOpenTextDocument::METHOD.to_string(),
+            lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem::new(
+                    uri.clone(),
+                    "plaintext".to_string(),
+                    1,
+                    "hello world".to_string(),
+                ),
+            },
+        );
+        backend.handle_notification(open).unwrap();
+        assert!(backend.docs.contains_key(&uri));
+
+        let close = Notification::new(
+            notification::DidCloseTextDocument::METHOD.to_string(),
+            lsp_types::DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+            },
+        );
+        backend.handle_notification(close).unwrap();
+        assert!(!backend.docs.contains_key(&uri));
+    }
+
+    #[test]
+    fn test_load_all_words_custom_pattern() {
+        let config = Config::from_value(Some(serde_json::json!({
+            "wordPattern": "[A-Za-z_][A-Za-z0-9_-]+"
+        })));
+        let words: HashSet<String> = load_all_words(".my-class { font-size: large }", &config)
+            .into_keys()
+            .collect();
+        let expected_words: HashSet<String> =
+            ["my-class", "font-size", "large"].map(String::from).into();
+        assert_eq!(words, expected_words);
+    }
+
+    #[test]
+    fn test_load_all_words_unicode() {
+        let words: HashSet<String> = load_all_words(
+            "un café naïve; let 変数名 = größe_1 -- ¿¡!? …",
+            &Config::default(),
+        )
+        .into_keys()
+        .collect();
+        let expected_words: HashSet<String> = ["un", "café", "naïve", "let", "変数名", "größe_1"]
+            .map(String::from)
+            .into();
+        assert_eq!(words, expected_words);
+    }
+
+    #[test]
+    fn test_word_prefix_unicode() {
+        assert_eq!(
+            word_prefix(
+                "let caf",
+                Position::new(0, 7),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "caf"
+        );
+        assert_eq!(
+            word_prefix(
+                "x.変数",
+                Position::new(0, 4),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "変数"
+        );
+        // Combining marks and connector punctuation continue a word, as in the tokenizer.
+        assert_eq!(
+            word_prefix(
+                "x cafe\u{301}",
+                Position::new(0, 7),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "cafe\u{301}"
+        );
+        assert_eq!(
+            word_prefix(
+                "x a\u{203f}b",
+                Position::new(0, 5),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "a\u{203f}b"
+        );
+        // Superscript digits are numeric but not part of a word.
+        assert_eq!(
+            word_prefix(
+                "x a\u{b2}",
+                Position::new(0, 4),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_load_all_words_min_word_length() {
+        let words = |settings: serde_json::Value| -> HashSet<String> {
+            load_all_words(
+                "fn main() { let x = 1; }",
+                &Config::from_value(Some(settings)),
+            )
+            .into_keys()
+            .collect()
+        };
+
+        let expected: HashSet<String> = ["fn", "main", "let"].map(String::from).into();
+        assert_eq!(words(serde_json::json!({})), expected);
+
+        let expected: HashSet<String> = ["main", "let"].map(String::from).into();
+        assert_eq!(words(serde_json::json!({ "minWordLength": 3 })), expected);
+
+        let expected: HashSet<String> = ["fn", "main", "let", "x"].map(String::from).into();
+        assert_eq!(words(serde_json::json!({ "minWordLength": -1 })), expected);
+
+        assert!(words(serde_json::json!({ "minWordLength": 100 })).is_empty());
+    }
+
+    #[test]
+    fn test_load_all_words_exclude_numbers() {
+        let words = |settings: serde_json::Value| -> HashSet<String> {
+            load_all_words("x1 = 42 + 0xFF * utf8", &Config::from_value(Some(settings)))
+                .into_keys()
+                .collect()
+        };
+        let pattern = serde_json::json!(r"\w+");
+
+        let expected: HashSet<String> = ["x1", "utf8"].map(String::from).into();
+        assert_eq!(
+            words(serde_json::json!({ "wordPattern": pattern })),
+            expected
+        );
+
+        let expected: HashSet<String> = ["x1", "42", "0xFF", "utf8"].map(String::from).into();
+        assert_eq!(
+            words(serde_json::json!({ "wordPattern": pattern, "excludeNumbers": false })),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_load_all_words_allow_leading_digits() {
+        let words = |settings: serde_json::Value| -> HashSet<String> {
+            load_all_words("2fa 42 3d value 0xFF", &Config::from_value(Some(settings)))
+                .into_keys()
+                .collect()
+        };
+
+        let expected: HashSet<String> = ["fa", "value", "xFF"].map(String::from).into();
+        assert_eq!(words(serde_json::json!({})), expected);
+
+        let expected: HashSet<String> = ["2fa", "3d", "value"].map(String::from).into();
+        assert_eq!(
+            words(serde_json::json!({ "allowLeadingDigits": true })),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_load_all_words_include_dots() {
+        let words = |settings: serde_json::Value| -> HashSet<String> {
+            load_all_words(
+                ".foo.bar. std.collections.map foo..bar",
+                &Config::from_value(Some(settings)),
+            )
+            .into_keys()
+            .collect()
+        };
+
+        let expected: HashSet<String> = ["foo", "bar", "std", "collections", "map"]
+            .map(String::from)
+            .into();
+        assert_eq!(words(serde_json::json!({})), expected);
+
+        let expected: HashSet<String> = [
+            "foo",
+            "bar",
+            "foo.bar",
+            "std",
+            "collections",
+            "map",
+            "std.collections.map",
+        ]
+        .map(String::from)
+        .into();
+        assert_eq!(words(serde_json::json!({ "includeDots": true })), expected);
+    }
+
+    #[test]
+    fn test_load_all_words_strips_bom() {
+        for pattern in [config::DEFAULT_WORD_PATTERN, r"\S+"] {
+            let config = Config::from_value(Some(serde_json::json!({ "wordPattern": pattern })));
+            let words: HashSet<String> = load_all_words("\u{FEFF}first second", &config)
+                .into_keys()
+                .collect();
+            let expected: HashSet<String> = ["first", "second"].map(String::from).into();
+            assert_eq!(words, expected);
+        }
+    }
+
+    #[test]
+    fn test_load_all_words_large_document() {
+        let content: String = (0..30_000)
+            .map(|i| format!("word{} other_{}\n", i % 101, i % 7))
+            .collect();
+        let config = Config::default();
+        let mut expected = HashMap::new();
+        for m in config.word_pattern.find_iter(&content) {
+            *expected.entry(m.as_str().to_string()).or_insert(0) += 1;
+        }
+        assert_eq!(load_all_words(&content, &config), expected);
+    }
+
+    #[test]
+    fn test_is_number() {
+        assert!(is_number("42"));
+        assert!(is_number("0xFF"));
+        assert!(is_number("0X1f"));
+        assert!(!is_number("0x"));
+        assert!(!is_number("x1"));
+        assert!(!is_number("utf8"));
+    }
+
+    #[test]
+    fn test_word_at() {
+        let content = "let test = 1;\nfoo.bar";
+        assert_eq!(
+            word_at(
+                content,
+                Position::new(0, 6),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "test"
+        );
+        assert_eq!(
+            word_at(
+                content,
+                Position::new(0, 8),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "test"
+        );
+        assert_eq!(
+            word_at(
+                content,
+                Position::new(0, 9),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            ""
+        );
+        assert_eq!(
+            word_at(
+                content,
+                Position::new(1, 4),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "bar"
+        );
+    }
+
+    #[test]
+    fn test_completion_excludes_word_under_cursor() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("let testing = 1; tes"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 0, 20),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["testing"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+
+        docs.insert(uri.clone(), doc("let test = 1; test"));
+        let response = create_completion_response(
+            completion_request(&uri, 0, 16),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["test"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_split_identifier() {
+        assert_eq!(split_identifier("getUserName"), ["get", "User", "Name"]);
+        assert_eq!(split_identifier("UserName"), ["User", "Name"]);
+        assert_eq!(split_identifier("user_id"), ["user", "id"]);
+        assert_eq!(split_identifier("HTTPServer"), ["HTTP", "Server"]);
+        assert!(split_identifier("plain").is_empty());
+        assert!(split_identifier("_private").is_empty());
+    }
+
+    #[test]
+    fn test_load_all_words_split_identifiers() {
+        let config = Config::from_value(Some(serde_json::json!({ "splitIdentifiers": true })));
+        let words: HashSet<String> = load_all_words("getUserName(user_id)", &config)
+            .into_keys()
+            .collect();
+        let expected_words: HashSet<String> = [
+            "getUserName",
+            "get",
+            "User",
+            "Name",
+            "user_id",
+            "user",
+            "id",
+        ]
+        .map(String::from)
+        .into();
+        assert_eq!(words, expected_words);
+    }
+
+    fn hover_request(uri: &Uri, line: u32, character: u32) -> Request {
+        Request {
+            id: 1.into(),
+            method: request::HoverRequest::METHOD.to_string(),
+            params: serde_json::to_value(HoverParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position { line, character },
+                },
+                work_done_progress_params: Default::default(),
+            })
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_hover_shows_occurrence_count() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("test(); testing;\ntest = test + test"));
+
+        let config = Config::default();
+        let Message::Response(resp) =
+            create_hover_response(hover_request(&uri, 1, 2), &docs, &config).unwrap()
+        else {
+            panic!("Expected a response message");
+        };
+        let hover: Hover = serde_json::from_value(resp.response_result.unwrap()).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("Expected markup contents");
+        };
+        assert_eq!(markup.value, "`test` — 4 occurrences");
+
+        let Message::Response(resp) =
+            create_hover_response(hover_request(&uri, 0, 6), &docs, &config).unwrap()
+        else {
+            panic!("Expected a response message");
+        };
+        assert!(resp.response_result.unwrap().is_null());
+    }
+
+    #[test]
+    fn test_cursor_word_follows_word_pattern() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let text = "font-size margin\nfont-";
+        let kebab = Config::from_value(Some(serde_json::json!({ "wordPattern": "[a-z][a-z-]*" })));
+        let css = Config::from_value(Some(serde_json::json!({
+            "languagePatterns": { "css": "[a-z][a-z-]*" },
+        })));
+        for config in [kebab, css] {
+            let mut docs = HashMap::new();
+            docs.insert(
+                uri.clone(),
+                Document::new(text.to_string(), "css".to_string(), &config),
+            );
+            let items = completion_items(
+                create_completion_response(
+                    completion_request(&uri, 1, 5),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    &config,
+                )
+                .unwrap(),
+            );
+            // `font-` is the prefix being typed, so it is replaced and not offered itself.
+            let labels = items
+                .iter()
+                .map(|item| item.label.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(labels, ["font-size"]);
+            assert_eq!(
+                items[0].text_edit,
+                Some(CompletionTextEdit::Edit(TextEdit::new(
+                    Range::new(Position::new(1, 0), Position::new(1, 5)),
+                    "font-size".to_string(),
+                )))
+            );
+
+            let Message::Response(resp) =
+                create_hover_response(hover_request(&uri, 0, 6), &docs, &config).unwrap()
+            else {
+                panic!("Expected a response message");
+            };
+            let hover: Hover = serde_json::from_value(resp.response_result.unwrap()).unwrap();
+            let HoverContents::Markup(markup) = hover.contents else {
+                panic!("Expected markup contents");
+            };
+            assert_eq!(markup.value, "`font-size` — 1 occurrence");
+        }
+    }
+
+    #[test]
+    fn test_completion_is_capped_at_max_items() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc("alpha beta gamma delta delta epsilon epsilon"),
+        );
+
+        let config = Config::from_value(Some(serde_json::json!({ "maxCompletionItems": 2 })));
+        let list = completion_list(
+            create_completion_response(
+                completion_request(&uri, 0, 0),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &config,
+            )
+            .unwrap(),
+        );
+        assert!(list.is_incomplete);
+        let labels: Vec<String> = list.items.into_iter().map(|item| item.label).collect();
+        assert_eq!(labels, ["delta", "epsilon"]);
+
+        let list = completion_list(
+            create_completion_response(
+                completion_request(&uri, 0, 0),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        assert!(!list.is_incomplete);
+        assert_eq!(list.items.len(), 5);
+    }
+
+    #[test]
+    fn test_completion_matches_prefix_case_insensitively() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("Test test Other\nte"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 1, 2),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["Test", "test"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+
+        let config = Config::from_value(Some(serde_json::json!({ "caseSensitive": true })));
+        let response = create_completion_response(
+            completion_request(&uri, 1, 2),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &config,
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["test"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_positions_follow_negotiated_encoding() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("😀 alpha alpha"));
+        let highlights = |encoding: Encoding, character: u32| {
+            let config = Config {
+                position_encoding: encoding,
+                ..Config::default()
+            };
+            let req = Request::new(
+                1.into(),
+                request::DocumentHighlightRequest::METHOD.to_string(),
+                DocumentHighlightParams {
+                    text_document_position_params: TextDocumentPositionParams {
+                        text_document: TextDocumentIdentifier { uri: uri.clone() },
+                        position: Position::new(0, character),
+                    },
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                },
+            );
+            let Message::Response(resp) =
+                create_document_highlight_response(req, &docs, &config).unwrap()
+            else {
+                panic!("Expected a response message");
+            };
+            serde_json::from_value::<Vec<DocumentHighlight>>(resp.response_result.unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|h| (h.range.start.character, h.range.end.character))
+                .collect::<Vec<_>>()
+        };
+        // The emoji is two UTF-16 code units but four UTF-8 bytes.
+        assert_eq!(highlights(Encoding::Utf16, 4), [(3, 8), (9, 14)]);
+        assert_eq!(highlights(Encoding::Utf8, 6), [(5, 10), (11, 16)]);
+
+        let config = Config {
+            position_encoding: Encoding::Utf8,
+            ..Config::default()
+        };
+        assert_eq!(
+            server_capabilities(&config).position_encoding,
+            Some(lsp_types::PositionEncodingKind::UTF8)
+        );
+    }
+
+    #[test]
+    fn test_document_highlight_ranges() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("let value = 1;\n  value + values"));
+
+        let req = Request {
+            id: 1.into(),
+            method: request::DocumentHighlightRequest::METHOD.to_string(),
+            params: serde_json::to_value(DocumentHighlightParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position::new(1, 4),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap(),
+        };
+        let Message::Response(resp) =
+            create_document_highlight_response(req, &docs, &Config::default()).unwrap()
+        else {
+            panic!("Expected a response message");
+        };
+        let highlights: Vec<DocumentHighlight> =
+            serde_json::from_value(resp.response_result.unwrap()).unwrap();
+        let ranges: Vec<Range> = highlights.iter().map(|h| h.range).collect();
+        assert_eq!(
+            ranges,
+            [
+                Range::new(Position::new(0, 4), Position::new(0, 9)),
+                Range::new(Position::new(1, 2), Position::new(1, 7)),
+            ]
+        );
+        assert!(
+            highlights
+                .iter()
+                .all(|h| h.kind == Some(DocumentHighlightKind::TEXT))
+        );
+    }
+
+    #[test]
+    fn test_word_occurrences_match_whole_words() {
+        let config = Config::default();
+        let content = "test testing attest test";
+        assert_eq!(
+            word_occurrences(content, "", "test", &config),
+            [
+                Range::new(Position::new(0, 0), Position::new(0, 4)),
+                Range::new(Position::new(0, 20), Position::new(0, 24)),
+            ]
+        );
+        assert_eq!(
+            word_occurrences("test", "", "test", &config),
+            [Range::new(Position::new(0, 0), Position::new(0, 4))]
+        );
+        assert!(word_occurrences("testing", "", "test", &config).is_empty());
+    }
+
+    #[test]
+    fn test_word_ranges_in_crlf_document() {
+        let content = "alpha\r\nbeta alpha\r\n";
+        assert_eq!(
+            word_occurrences(content, "", "alpha", &Config::default()),
+            [
+                Range::new(Position::new(0, 0), Position::new(0, 5)),
+                Range::new(Position::new(1, 5), Position::new(1, 10)),
+            ]
+        );
+        assert_eq!(
+            word_at(
+                content,
+                Position::new(1, 7),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "alpha"
+        );
+        assert_eq!(
+            word_prefix(
+                content,
+                Position::new(0, 99),
+                Encoding::Utf16,
+                &Config::default().word_pattern
+            ),
+            "alpha"
+        );
+    }
+
+    #[test]
+    fn test_references_across_documents() {
+        let first = "file:///first".parse::<Uri>().unwrap();
+        let second = "file:///second".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(first.clone(), doc("let café = shared;"));
+        docs.insert(second.clone(), doc("/* é */ shared()"));
+
+        let req = Request {
+            id: 1.into(),
+            method: request::References::METHOD.to_string(),
+            params: serde_json::to_value(ReferenceParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: first.clone() },
+                    position: Position::new(0, 12),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: lsp_types::ReferenceContext {
+                    include_declaration: true,
+                },
+            })
+            .unwrap(),
+        };
+        let Message::Response(resp) =
+            create_references_response(req, &docs, &Config::default()).unwrap()
+        else {
+            panic!("Expected a response message");
+        };
+        let locations: Vec<Location> =
+            serde_json::from_value(resp.response_result.unwrap()).unwrap();
+        assert_eq!(
+            locations,
+            [
+                Location::new(
+                    first,
+                    Range::new(Position::new(0, 11), Position::new(0, 17))
+                ),
+                Location::new(
+                    second,
+                    Range::new(Position::new(0, 8), Position::new(0, 14))
+                ),
+            ]
+        );
+    }
+
+    fn rename_request(uri: &Uri, position: Position, new_name: &str) -> Request {
+        Request {
+            id: 1.into(),
+            method: request::Rename::METHOD.to_string(),
+            params: serde_json::to_value(RenameParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position,
+                },
+                new_name: new_name.to_string(),
+                work_done_progress_params: Default::default(),
+            })
+            .unwrap(),
+        }
+    }
+
+    fn rename_result(response: Message) -> Option<WorkspaceEdit> {
+        let Message::Response(resp) = response else {
+            panic!("Expected a response message");
+        };
+        serde_json::from_value(resp.response_result.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_rename_replaces_whole_words() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let other = "file:///other".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc("count = count + 1;\nrecount(count_all, count)"),
+        );
+        docs.insert(other.clone(), doc("count"));
+
+        let response = create_rename_response(
+            rename_request(&uri, Position::new(0, 2), "total"),
+            &docs,
+            &Config::default(),
+        )
+        .unwrap();
+        let changes = rename_result(response).unwrap().changes.unwrap();
+        assert_eq!(changes.len(), 1);
+        let edits = &changes[&uri];
+        assert_eq!(edits.len(), 3);
+        assert!(edits.iter().all(|edit| edit.new_text == "total"));
+        assert_eq!(
+            edits[2].range,
+            Range::new(Position::new(1, 19), Position::new(1, 24))
+        );
+
+        let config = Config::from_value(Some(serde_json::json!({ "renameAllDocuments": true })));
+        let response = create_rename_response(
+            rename_request(&uri, Position::new(0, 2), "total"),
+            &docs,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            rename_result(response).unwrap().changes.unwrap()[&other].len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_rename_off_word_returns_none() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("count = 1;"));
+
+        let response = create_rename_response(
+            rename_request(&uri, Position::new(0, 7), "total"),
+            &docs,
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(rename_result(response).is_none());
+    }
+
+    #[test]
+    fn test_document_symbols_are_unique_first_occurrences() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        let text = "let beta = alpha;\nalpha = beta + gamma";
+        docs.insert(uri.clone(), doc(text));
+
+        let req = Request {
+            id: 1.into(),
+            method: request::DocumentSymbolRequest::METHOD.to_string(),
+            params: serde_json::to_value(DocumentSymbolParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap(),
+        };
+        let Message::Response(resp) =
+            create_document_symbol_response(req, &docs, &Config::default()).unwrap()
+        else {
+            panic!("Expected a response message");
+        };
+        let Some(DocumentSymbolResponse::Nested(symbols)) =
+            serde_json::from_value(resp.response_result.unwrap()).unwrap()
+        else {
+            panic!("Expected nested document symbols");
+        };
+        assert_eq!(
+            symbols.len(),
+            load_all_words(text, &Config::default()).len()
+        );
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["let", "beta", "alpha", "gamma"]);
+        assert_eq!(
+            symbols[2].range,
+            Range::new(Position::new(0, 11), Position::new(0, 16))
+        );
+        assert_eq!(
+            symbols[3].range,
+            Range::new(Position::new(1, 15), Position::new(1, 20))
+        );
+        assert!(symbols.iter().all(|s| s.kind == SymbolKind::VARIABLE));
+
+        // The filters of the word index apply too.
+        let config = Config::from_value(Some(serde_json::json!({
+            "splitIdentifiers": true,
+            "excludeNumbers": true,
+            "stopWords": ["LET"],
+        })));
+        let text = "let userId = 42;";
+        let names = first_occurrences(text, "", &config)
+            .into_iter()
+            .map(|(name, range)| (name, range.start.character))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("userId".to_string(), 4),
+                ("user".to_string(), 4),
+                ("Id".to_string(), 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc(
+                "/* A note\n   on two lines */\nfn f() {\n    g({ x });\n    h(\"{\", '}', \"/*\");\n}\n} { // {\n",
+            ),
+        );
+        let req = Request::new(
+            1.into(),
+            request::FoldingRangeRequest::METHOD.to_string(),
+            FoldingRangeParams {
+                text_document: TextDocumentIdentifier { uri },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        );
+        let Message::Response(resp) =
+            create_folding_range_response(req, &docs, &Config::default()).unwrap()
+        else {
+            panic!("Expected a response message");
+        };
+        let ranges =
+            serde_json::from_value::<Option<Vec<FoldingRange>>>(resp.response_result.unwrap())
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            ranges,
+            [
+                FoldingRange {
+                    start_line: 0,
+                    end_line: 1,
+                    kind: Some(FoldingRangeKind::Comment),
+                    ..Default::default()
+                },
+                FoldingRange {
+                    start_line: 2,
+                    end_line: 5,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("let fooBar = 1;\r\nnext line"));
+        let selection_ranges = |uri: &Uri, positions: Vec<Position>| {
+            let req = Request::new(
+                1.into(),
+                request::SelectionRangeRequest::METHOD.to_string(),
+                SelectionRangeParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    positions,
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                },
+            );
+            let Message::Response(resp) =
+                create_selection_range_response(req, &docs, &Config::default()).unwrap()
+            else {
+                panic!("Expected a response message");
+            };
+            serde_json::from_value::<Option<Vec<SelectionRange>>>(resp.response_result.unwrap())
+                .unwrap()
+        };
+        let chain = |mut range: &SelectionRange| {
+            let mut ranges = vec![range.range];
+            while let Some(parent) = &range.parent {
+                ranges.push(parent.range);
+                range = parent;
+            }
+            ranges
+        };
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+        };
+
+        let ranges =
+            selection_ranges(&uri, vec![Position::new(0, 6), Position::new(0, 11)]).unwrap();
+        assert_eq!(
+            chain(&ranges[0]),
+            [
+                range((0, 4), (0, 10)),
+                range((0, 0), (0, 15)),
+                range((0, 0), (1, 9))
+            ]
+        );
+        // Between words, the line is the smallest range.
+        assert_eq!(
+            chain(&ranges[1]),
+            [range((0, 0), (0, 15)), range((0, 0), (1, 9))]
+        );
+
+        let unopened = "file:///unopened".parse::<Uri>().unwrap();
+        assert_eq!(selection_ranges(&unopened, vec![Position::new(0, 0)]), None);
+    }
+
+    #[test]
+    fn test_workspace_symbols_across_documents() {
+        let first = "file:///first".parse::<Uri>().unwrap();
+        let second = "file:///second".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(first.clone(), doc("let userName = 1;"));
+        docs.insert(second.clone(), doc("print(username, other)"));
+
+        let symbols = |req, workspace: &WorkspaceIndex| {
+            let Message::Response(resp) =
+                create_workspace_symbol_response(req, &docs, workspace, &Config::default())
+                    .unwrap()
+            else {
+                panic!("Expected a response message");
+            };
+            let Some(WorkspaceSymbolResponse::Flat(symbols)) =
+                serde_json::from_value(resp.response_result.unwrap()).unwrap()
+            else {
+                panic!("Expected flat workspace symbols");
+            };
+            symbols
+        };
+        let req = Request {
+            id: 1.into(),
+            method: request::WorkspaceSymbolRequest::METHOD.to_string(),
+            params: serde_json::to_value(WorkspaceSymbolParams {
+                query: "USERN".to_string(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap(),
+        };
+        let symbols = symbols(req.clone(), &WorkspaceIndex::default());
+        assert!(symbols.iter().all(|s| s.container_name.is_none()));
+        let locations: Vec<Location> = symbols.into_iter().map(|s| s.location).collect();
+        assert_eq!(
+            locations,
+            [
+                Location::new(first, Range::new(Position::new(0, 4), Position::new(0, 12))),
+                Location::new(
+                    second,
+                    Range::new(Position::new(0, 6), Position::new(0, 14))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workspace_symbols_name_their_folder() {
+        let root = std::env::temp_dir().join("lsp-word-test-symbol-folder/app");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("notes.txt");
+        fs::write(&path, "alpha").unwrap();
+        let workspace = WorkspaceIndex::scan(std::slice::from_ref(&root), &Config::default());
+        let mut backend = Backend::new(Config::default(), Arc::new(Mutex::new(workspace)));
+        let uri = format!("file://{}", path.display()).parse::<Uri>().unwrap();
+        backend
+            .handle_notification(did_open(&uri, "alpha"))
+            .unwrap();
+
+        let req = Request {
+            id: 1.into(),
+            method: request::WorkspaceSymbolRequest::METHOD.to_string(),
+            params: serde_json::json!({ "query": "alpha" }),
+        };
+        let Message::Response(resp) = backend.respond(req) else {
+            panic!("Expected a response message");
+        };
+        let Some(WorkspaceSymbolResponse::Flat(symbols)) =
+            serde_json::from_value(resp.response_result.unwrap()).unwrap()
+        else {
+            panic!("Expected flat workspace symbols");
+        };
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].container_name.as_deref(), Some("app"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_completion_items_have_kind() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("let answer = 42;"));
+
+        let items = completion_items(
+            create_completion_response(
+                completion_request(&uri, 0, 0),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        let kind = |label: &str| items.iter().find(|item| item.label == label).unwrap().kind;
+        assert_eq!(kind("answer"), Some(CompletionItemKind::TEXT));
+        assert_eq!(kind("let"), Some(CompletionItemKind::KEYWORD));
+    }
+
+    #[test]
+    fn test_completion_includes_dictionary_words() {
+        let path = std::env::temp_dir().join("lsp-word-test-completion-dictionary.txt");
+        fs::write(&path, "tangerine\ntomato\napple\n").unwrap();
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("let tomato = t"));
+
+        let config = Config::from_value(Some(serde_json::json!({ "dictionaryPath": path })));
+        let items = completion_items(
+            create_completion_response(
+                completion_request(&uri, 0, 14),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &config,
+            )
+            .unwrap(),
+        );
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["tomato", "tangerine"]);
+    }
+
+    #[test]
+    fn test_completion_includes_workspace_words() {
+        let root = std::env::temp_dir().join("lsp-word-test-completion-workspace");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/one.rs"), "fn workspace_only() {}").unwrap();
+        fs::write(root.join("two.md"), "workspace notes").unwrap();
+        let config = Config::default();
+        let workspace = WorkspaceIndex::scan(&[root], &config);
+
+        let uri = "file:///unrelated".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("wor"));
+        let response = create_completion_response(
+            completion_request(&uri, 0, 3),
+            &mut docs,
+            &workspace,
+            &WordIndex::default(),
+            &config,
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["workspace_only", "workspace"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_completion_shows_source_file() {
+        let root = std::env::temp_dir().join("lsp-word-test-show-source");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("disk.txt"), "on_disk on_disk on_disk").unwrap();
+        let mut config = Config::from_value(Some(serde_json::json!({ "showSource": true })));
+        let workspace = WorkspaceIndex::scan(&[root], &config);
+
+        let notes = "file:///docs/notes.txt".parse::<Uri>().unwrap();
+        let lib = "file:///src/lib.rs".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(notes.clone(), doc("shared own on_disk\n"));
+        docs.insert(lib, doc("shared shared"));
+        let mut sources = |config: &Config| -> HashMap<String, Option<String>> {
+            completion_items(
+                create_completion_response(
+                    completion_request(&notes, 1, 0),
+                    &mut docs,
+                    &workspace,
+                    &WordIndex::default(),
+                    config,
+                )
+                .unwrap(),
+            )
+            .into_iter()
+            .map(|item| {
+                (
+                    item.label,
+                    item.label_details.and_then(|details| details.description),
+                )
+            })
+            .collect()
+        };
+
+        let expected = [
+            ("shared", "lib.rs"),
+            ("own", "notes.txt"),
+            ("on_disk", "disk.txt"),
+        ]
+        .map(|(word, name)| (word.to_string(), Some(name.to_string())));
+        assert_eq!(sources(&config), expected.into());
+
+        config.show_source = false;
+        assert!(sources(&config).values().all(Option::is_none));
+    }
+
+    fn did_open(uri: &Uri, text: &str) -> Notification {
+        did_open_language(uri, "plaintext", text)
+    }
+
+    fn did_open_language(uri: &Uri, language_id: &str, text: &str) -> Notification {
+        Notification::new(
+            notification::DidOpenTextDocument::METHOD.to_string(),
+            lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem::new(
+                    uri.clone(),
+                    language_id.to_string(),
+                    1,
+                    text.to_string(),
+                ),
+            },
+        )
+    }
+
+    fn did_change(
+        uri: &Uri,
+        changes: Vec<lsp_types::TextDocumentContentChangeEvent>,
+    ) -> Notification {
+        Notification::new(
+            notification::DidChangeTextDocument::METHOD.to_string(),
+            lsp_types::DidChangeTextDocumentParams {
+                text_document: lsp_types::VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                content_changes: changes,
+            },
+        )
+    }
+
+    fn full_change(text: &str) -> lsp_types::TextDocumentContentChangeEvent {
+        lsp_types::TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    impl Backend {
+        /// Runs every pending rebuild as if the debounce period had elapsed.
+        fn settle(&mut self) -> usize {
+            self.reindex_due(Instant::now() + REINDEX_DEBOUNCE)
+        }
+
+        fn respond(&mut self, req: Request) -> Message {
+            self.handle_request(req).unwrap().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_rapid_changes_are_reindexed_once() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+
+        backend
+            .handle_notification(did_open(&uri, "alpha"))
+            .unwrap();
+        for text in ["beta", "gamma", "delta"] {
+            backend
+                .handle_notification(did_change(&uri, vec![full_change(text)]))
+                .unwrap();
+        }
+        // Until the burst settles, completion still sees the previous words.
+        assert_eq!(backend.reindex_due(Instant::now()), 0);
+        assert_eq!(backend.docs[&uri].words.prefix_completions(""), ["alpha"]);
+        assert!(backend.next_reindex().is_some());
+
+        assert_eq!(backend.settle(), 1);
+        assert_eq!(backend.docs[&uri].words.prefix_completions(""), ["delta"]);
+        assert_eq!(backend.next_reindex(), None);
+        assert_eq!(backend.settle(), 0);
+    }
+
+    #[test]
+    fn test_completion_ranks_recently_typed_words_first() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        for notification in [
+            did_open(&uri, "alpha beta\n"),
+            did_change(&uri, vec![full_change("alpha beta zeta\n")]),
+        ] {
+            backend.handle_notification(notification).unwrap();
+        }
+        backend.settle();
+
+        let response = backend.respond(completion_request(&uri, 1, 0));
+        let labels: Vec<String> = completion_items(response)
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, ["zeta", "alpha", "beta"]);
+    }
+
+    fn ranged_change(
+        start: (u32, u32),
+        end: (u32, u32),
+        text: &str,
+    ) -> lsp_types::TextDocumentContentChangeEvent {
+        lsp_types::TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn word_counts(index: &WordIndex) -> Vec<(String, usize)> {
+        index
+            .iter()
+            .map(|(word, count)| (word.to_string(), count))
+            .collect()
+    }
+
+    #[test]
+    fn test_schedule_reindex_waits_at_most_from_first_edit() {
+        let config = Config::default();
+        let mut doc = Document::new("alpha".to_string(), String::new(), &config);
+        doc.schedule_reindex();
+        let first = doc.edited_at.unwrap();
+        assert_eq!(doc.reindex_at, Some(first + REINDEX_DEBOUNCE));
+
+        // After a second of continuous typing, the next edit no longer pushes the rebuild back.
+        let started = first.checked_sub(REINDEX_MAX_WAIT).unwrap();
+        doc.edited_at = Some(started);
+        doc.schedule_reindex();
+        assert_eq!(doc.edited_at, Some(started));
+        assert_eq!(doc.reindex_at, Some(first));
+
+        doc.reindex_edited(&config, &mut WordIndex::default());
+        assert_eq!(doc.edited_at, None);
+    }
+
+    #[test]
+    fn test_ranged_change_updates_words_in_place() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "alpha beta\nunique gamma\nbeta"))
+            .unwrap();
+
+        // Deletes the only `unique`, without waiting for the edits to settle.
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((1, 0), (1, 7), "")]))
+            .unwrap();
+        let doc = &backend.docs[&uri];
+        assert_eq!(doc.text, "alpha beta\ngamma\nbeta");
+        assert_eq!(doc.reindex_at, None);
+        assert_eq!(
+            word_counts(&doc.words),
+            [("alpha".into(), 1), ("beta".into(), 2), ("gamma".into(), 1)]
+        );
+        assert!(!backend.open_words.contains("unique"));
+
+        // Joining lines merges their words.
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((0, 10), (1, 0), "")]))
+            .unwrap();
+        assert_eq!(
+            word_counts(&backend.docs[&uri].words),
+            [
+                ("alpha".into(), 1),
+                ("beta".into(), 1),
+                ("betagamma".into(), 1)
+            ]
+        );
+        assert_eq!(backend.docs[&uri].recent, ["betagamma"]);
+    }
+
+    #[test]
+    fn test_ranged_changes_match_full_reindex() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let config = Config::from_value(Some(serde_json::json!({ "splitIdentifiers": true })));
+        let mut backend = Backend::new(config, Default::default());
+        backend
+            .handle_notification(did_open(&uri, "fooBar baz\nqux.quux 42\n\nend"))
+            .unwrap();
+        for change in [
+            ranged_change((0, 3), (0, 3), "Bar"),
+            ranged_change((1, 3), (1, 4), " "),
+            ranged_change((2, 0), (2, 0), "new words\nand lines "),
+            ranged_change((0, 0), (4, 1), "x"),
+            ranged_change((0, 1), (0, 1), "y z"),
+        ] {
+            backend
+                .handle_notification(did_change(&uri, vec![change]))
+                .unwrap();
+            let doc = &backend.docs[&uri];
+            let expected = WordIndex::from(load_all_words(&doc.text, &backend.config));
+            assert_eq!(
+                word_counts(&doc.words),
+                word_counts(&expected),
+                "{:?}",
+                doc.text
+            );
+            assert_eq!(word_counts(&backend.open_words), word_counts(&expected));
+        }
+    }
+
+    #[test]
+    fn test_incomplete_rerequest_narrows_cached_candidates() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let config = Config::from_value(Some(serde_json::json!({ "maxCompletionItems": 1 })));
+        let mut backend = Backend::new(config, Default::default());
+        backend
+            .handle_notification(did_open(&uri, "alpha alpine alps beta\n"))
+            .unwrap();
+        let candidates = |backend: &Backend| {
+            backend.docs[&uri]
+                .candidates
+                .as_ref()
+                .map(|cached| (cached.prefix.clone(), cached.words.clone()))
+        };
+
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((1, 0), (1, 0), "al")]))
+            .unwrap();
+        let list = completion_list(backend.respond(triggered_completion_request(
+            &uri,
+            1,
+            2,
+            CompletionTriggerKind::INVOKED,
+        )));
+        assert!(list.is_incomplete);
+        assert_eq!(
+            candidates(&backend),
+            Some((
+                "al".to_string(),
+                vec!["al".into(), "alpha".into(), "alpine".into(), "alps".into()]
+            ))
+        );
+
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((1, 2), (1, 2), "pi")]))
+            .unwrap();
+        // A word the re-request would find if it searched the document again.
+        backend.docs.get_mut(&uri).unwrap().words.add("alpaca", 1);
+        let list = completion_list(backend.respond(triggered_completion_request(
+            &uri,
+            1,
+            4,
+            CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS,
+        )));
+        assert_eq!(
+            list.items
+                .iter()
+                .map(|item| &item.label)
+                .collect::<Vec<_>>(),
+            ["alpine"]
+        );
+        assert_eq!(
+            candidates(&backend),
+            Some(("alpi".to_string(), vec!["alpine".into()]))
+        );
+
+        // Editing elsewhere in the document drops the candidates.
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((0, 0), (0, 0), "x ")]))
+            .unwrap();
+        assert_eq!(candidates(&backend), None);
+    }
+
+    #[test]
+    fn test_ranged_change_with_multiline_pattern_is_deferred() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let config = Config::from_value(Some(serde_json::json!({ "wordPattern": "[a-z\\n]+" })));
+        let mut backend = Backend::new(config, Default::default());
+        backend
+            .handle_notification(did_open(&uri, "ab\ncd"))
+            .unwrap();
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((1, 0), (1, 0), "x")]))
+            .unwrap();
+        assert!(backend.docs[&uri].reindex_at.is_some());
+        backend.settle();
+        assert_eq!(
+            word_counts(&backend.docs[&uri].words),
+            [("ab\nxcd".into(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_recent_words_skip_partially_typed_words() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "alpha\n"))
+            .unwrap();
+        for (i, c) in "hello".chars().enumerate() {
+            let at = (1, i as u32);
+            backend
+                .handle_notification(did_change(
+                    &uri,
+                    vec![ranged_change(at, at, &c.to_string())],
+                ))
+                .unwrap();
+        }
+        assert_eq!(backend.docs[&uri].recent, ["hello"]);
+
+        // Deleting the word forgets it too.
+        backend
+            .handle_notification(did_change(&uri, vec![ranged_change((1, 0), (1, 5), "")]))
+            .unwrap();
+        assert!(backend.docs[&uri].recent.is_empty());
+    }
+
+    #[test]
+    fn test_recent_words_are_bounded() {
+        let mut doc = doc("");
+        let config = Config::default();
+        for i in 0..MAX_RECENT_WORDS + 5 {
+            doc.text.push_str(&format!(" word{i}"));
+            doc.reindex_edited(&config, &mut WordIndex::default());
+        }
+        assert_eq!(doc.recent.len(), MAX_RECENT_WORDS);
+        assert_eq!(doc.recent.front().map(String::as_str), Some("word5"));
+        assert_eq!(
+            doc.recent.back().cloned(),
+            Some(format!("word{}", MAX_RECENT_WORDS + 4))
+        );
+    }
+
+    #[test]
+    fn test_word_cache_tracks_open_and_change() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+
+        backend
+            .handle_notification(did_open(&uri, "alpha beta"))
+            .unwrap();
+        assert_eq!(
+            backend.docs[&uri].words.prefix_completions(""),
+            ["alpha", "beta"]
+        );
+
+        backend
+            .handle_notification(did_change(&uri, vec![full_change("gamma")]))
+            .unwrap();
+        backend.settle();
+        assert_eq!(backend.docs[&uri].words.prefix_completions(""), ["gamma"]);
+    }
+
+    #[test]
+    fn test_did_change_applies_every_change_in_batch() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+
+        backend
+            .handle_notification(did_open(&uri, "alpha"))
+            .unwrap();
+        backend
+            .handle_notification(did_change(
+                &uri,
+                vec![full_change("first"), full_change("second")],
+            ))
+            .unwrap();
+        assert_eq!(backend.docs[&uri].text, "second");
+        backend.settle();
+        assert_eq!(backend.docs[&uri].words.prefix_completions(""), ["second"]);
+    }
+
+    #[test]
+    fn test_completion_reads_word_cache() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        let mut cached = doc("cached");
+        // Only the text changes; completion must still answer from the cached words.
+        cached.text = "uncached".to_string();
+        docs.insert(uri.clone(), cached);
+
+        let response = create_completion_response(
+            completion_request(&uri, 0, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["cached"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_added_words_notifications() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "domain\ndom"))
+            .unwrap();
+        let labels = |backend: &mut Backend| {
+            let mut items = completion_items(backend.respond(completion_request(&uri, 1, 3)));
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+        };
+
+        backend
+            .handle_notification(Notification::new(
+                ADD_WORDS_METHOD.to_string(),
+                serde_json::json!({ "words": ["dominion", "domainModel", "other"] }),
+            ))
+            .unwrap();
+        assert_eq!(labels(&mut backend), ["domain", "domainModel", "dominion"]);
+
+        backend
+            .handle_notification(Notification::new(
+                CLEAR_WORDS_METHOD.to_string(),
+                serde_json::Value::Null,
+            ))
+            .unwrap();
+        assert_eq!(labels(&mut backend), ["domain"]);
+    }
+
+    #[test]
+    fn test_apply_settings_rescans_only_for_word_settings() {
+        let root = std::env::temp_dir().join("lsp-word-test-apply-settings");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("notes.txt"), "go gopher").unwrap();
+        let workspace = Arc::new(Mutex::new(WorkspaceIndex::scan(
+            &[root],
+            &Config::default(),
+        )));
+        let mut backend = Backend::new(Config::default(), Arc::clone(&workspace));
+
+        // Neither null, repeated nor ranking settings re-read the workspace or documents.
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        backend.handle_notification(did_open(&uri, "go")).unwrap();
+        backend
+            .handle_notification(did_change(&uri, vec![full_change("go gopher")]))
+            .unwrap();
+        assert!(backend.apply_settings(serde_json::Value::Null).is_none());
+        assert!(backend.apply_settings(serde_json::json!({})).is_none());
+        assert!(
+            backend
+                .apply_settings(serde_json::json!({ "fuzzy": true }))
+                .is_none()
+        );
+        let words = || workspace.lock().unwrap().words().prefix_completions("");
+        assert_eq!(words(), ["go", "gopher"]);
+        assert!(backend.docs[&uri].reindex_at.is_some());
+
+        backend
+            .apply_settings(serde_json::json!({ "minWordLength": 3 }))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(words(), ["gopher"]);
+        assert!(backend.docs[&uri].reindex_at.is_none());
+        backend
+            .apply_settings(serde_json::json!({ "maxFileSizeBytes": 1 }))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(words().is_empty());
+    }
+
+    #[test]
+    fn test_did_change_configuration_updates_completion() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "fn main"))
+            .unwrap();
+
+        let change_configuration = |settings: serde_json::Value| {
+            Notification::new(
+                notification::DidChangeConfiguration::METHOD.to_string(),
+                lsp_types::DidChangeConfigurationParams { settings },
+            )
+        };
+        backend
+            .handle_notification(change_configuration(
+                serde_json::json!({ "lsp-word": { "minWordLength": 3 } }),
+            ))
+            .unwrap();
+        assert_eq!(backend.config.min_word_length, 3);
+        let response = backend.respond(completion_request(&uri, 0, 0));
+        let expected: HashSet<String> = ["main"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+
+        backend
+            .handle_notification(change_configuration(
+                serde_json::json!({ "minWordLength": "short" }),
+            ))
+            .unwrap();
+        assert_eq!(backend.config.min_word_length, 3);
+    }
+
+    #[test]
+    fn test_completion_for_unopened_document_is_empty() {
+        let uri = "file:///unopened".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert("file:///other".parse::<Uri>().unwrap(), doc("other words"));
+
+        let config = Config::from_value(Some(serde_json::json!({ "allDocuments": false })));
+        let response = create_completion_response(
+            completion_request(&uri, 0, 0),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &config,
+        )
+        .unwrap();
+        assert!(completion_labels(response).is_empty());
+    }
+
+    #[test]
+    fn test_serve_survives_failing_requests() {
+        let (server, client) = Connection::memory();
+        let config = Config::from_value(Some(serde_json::json!({ "allDocuments": false })));
+        let handle =
+            std::thread::spawn(move || serve(server, config, Default::default(), None, None));
+
+        client
+            .sender
+            .send(Message::Request(Request::new(
+                1.into(),
+                request::Completion::METHOD.to_string(),
+                serde_json::json!({ "unexpected": true }),
+            )))
+            .unwrap();
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(
+            resp.response_result.unwrap_err().code,
+            ErrorCode::InvalidParams as i32
+        );
+
+        let uri = "file:///unopened".parse::<Uri>().unwrap();
+        let mut req = completion_request(&uri, 0, 0);
+        req.id = 2.into();
+        client.sender.send(Message::Request(req)).unwrap();
+        assert!(completion_labels(client.receiver.recv().unwrap()).is_empty());
+
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_serve_answers_superseded_requests_with_content_modified() {
+        let (server, client) = Connection::memory();
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        for msg in [
+            did_open(&uri, "alpha").into(),
+            Message::Request(completion_request(&uri, 0, 0)),
+            did_change(&uri, vec![full_change("alpha beta")]).into(),
+        ] {
+            client.sender.send(msg).unwrap();
+        }
+        // Everything is queued before the server starts, so it sees the edit in time.
+        let handle = std::thread::spawn(move || {
+            serve(server, Config::default(), Default::default(), None, None)
+        });
+
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(resp.id, 1.into());
+        assert_eq!(
+            resp.response_result.unwrap_err().code,
+            ErrorCode::ContentModified as i32
+        );
+
+        let mut req = completion_request(&uri, 0, 0);
+        req.id = 2.into();
+        client.sender.send(Message::Request(req)).unwrap();
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(resp.id, 2.into());
+        assert!(resp.response_result.is_ok());
+
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_serve_skips_cancelled_requests() {
+        let (server, client) = Connection::memory();
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        for msg in [
+            did_open(&uri, "alpha beta").into(),
+            Message::Request(completion_request(&uri, 0, 0)),
+            Notification::new(
+                notification::Cancel::METHOD.to_string(),
+                CancelParams {
+                    id: NumberOrString::Number(1),
+                },
+            )
+            .into(),
+        ] {
+            client.sender.send(msg).unwrap();
+        }
+        // Everything is queued before the server starts, so it sees the cancellation in time.
+        let handle = std::thread::spawn(move || {
+            serve(server, Config::default(), Default::default(), None, None)
+        });
+
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(resp.id, 1.into());
+        assert_eq!(
+            resp.response_result.unwrap_err().code,
+            ErrorCode::RequestCanceled as i32
+        );
+
+        let mut req = completion_request(&uri, 0, 0);
+        req.id = 2.into();
+        client.sender.send(Message::Request(req)).unwrap();
+        let expected: HashSet<String> = ["alpha", "beta"].map(String::from).into();
+        assert_eq!(completion_labels(client.receiver.recv().unwrap()), expected);
+
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    fn exit_notification() -> Message {
+        Notification::new(notification::Exit::METHOD.to_string(), ()).into()
+    }
+
+    #[test]
+    fn test_serve_stops_on_exit_after_shutdown() {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || {
+            serve(server, Config::default(), Default::default(), None, None)
+        });
+
+        let shutdown = Request::new(1.into(), request::Shutdown::METHOD.to_string(), ());
+        client.sender.send(shutdown.into()).unwrap();
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert!(resp.response_result.is_ok());
+
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut req = completion_request(&uri, 0, 0);
+        req.id = 2.into();
+        client.sender.send(req.into()).unwrap();
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(
+            resp.response_result.unwrap_err().code,
+            ErrorCode::InvalidRequest as i32
+        );
+
+        // The client stays connected, so only the exit notification can end the loop.
+        client.sender.send(exit_notification()).unwrap();
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_serve_fails_on_exit_without_shutdown() {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || {
+            serve(server, Config::default(), Default::default(), None, None)
+        });
+        client.sender.send(exit_notification()).unwrap();
+        assert!(handle.join().unwrap().is_err());
+    }
+
+    fn did_save(uri: &Uri, text: Option<&str>) -> Notification {
+        Notification::new(
+            notification::DidSaveTextDocument::METHOD.to_string(),
+            lsp_types::DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                text: text.map(String::from),
+            },
+        )
+    }
+
+    #[test]
+    fn test_did_save_refreshes_document() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "before"))
+            .unwrap();
+
+        backend
+            .handle_notification(did_save(&uri, Some("after save")))
+            .unwrap();
+        assert_eq!(backend.docs[&uri].text, "after save");
+        assert_eq!(
+            backend.docs[&uri].words.prefix_completions(""),
+            ["after", "save"]
+        );
+    }
+
+    #[test]
+    fn test_language_patterns_follow_document_language() {
+        let css = "file:///style.css".parse::<Uri>().unwrap();
+        let rust = "file:///main.rs".parse::<Uri>().unwrap();
+        let config = Config::from_value(Some(serde_json::json!({
+            "languagePatterns": { "css": "[a-z][a-z-]*" },
+        })));
+        let mut backend = Backend::new(config, Default::default());
+        let text = "font-size: large-ish";
+        backend
+            .handle_notification(did_open_language(&css, "css", text))
+            .unwrap();
+        backend
+            .handle_notification(did_open_language(&rust, "rust", text))
+            .unwrap();
+
+        assert_eq!(
+            backend.docs[&css].words.prefix_completions(""),
+            ["font-size", "large-ish"]
+        );
+        assert_eq!(
+            backend.docs[&rust].words.prefix_completions(""),
+            ["font", "ish", "large", "size"]
+        );
+
+        // Edits re-tokenize with the document's own pattern too.
+        backend
+            .handle_notification(did_change(
+                &css,
+                vec![ranged_change((0, 11), (0, 20), "x-large")],
+            ))
+            .unwrap();
+        assert_eq!(
+            backend.docs[&css].words.prefix_completions(""),
+            ["font-size", "x-large"]
+        );
+    }
+
+    #[test]
+    fn test_did_save_without_text_reads_from_disk() {
+        let path = std::env::temp_dir().join("lsp-word-test-did-save.txt");
+        fs::write(&path, "formatted on disk").unwrap();
+        let uri = format!("file://{}", path.display()).parse::<Uri>().unwrap();
+        let untitled = "untitled:Untitled-1".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "stale"))
+            .unwrap();
+        backend
+            .handle_notification(did_open(&untitled, "scratch"))
+            .unwrap();
+
+        backend.handle_notification(did_save(&uri, None)).unwrap();
+        backend
+            .handle_notification(did_save(&untitled, None))
+            .unwrap();
+        assert_eq!(backend.docs[&uri].text, "formatted on disk");
+        assert_eq!(backend.docs[&untitled].text, "scratch");
+
+        // Saving a document that was never opened does not open it.
+        let unopened = "file:///unopened".parse::<Uri>().unwrap();
+        backend
+            .handle_notification(did_save(&unopened, Some("phantom words")))
+            .unwrap();
+        assert!(!backend.docs.contains_key(&unopened));
+        assert!(!backend.open_words.contains("phantom"));
+    }
+
+    #[test]
+    fn test_completion_reads_unopened_file_from_disk() {
+        let path = std::env::temp_dir().join("lsp-word-test-unopened.txt");
+        fs::write(&path, "lazily opened words\nlaz").unwrap();
+        let uri = format!("file://{}", path.display()).parse::<Uri>().unwrap();
+        let untitled = "untitled:Untitled-1".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+
+        let labels = completion_labels(backend.respond(completion_request(&uri, 1, 3)));
+        assert_eq!(labels, HashSet::from(["lazily".to_string()]));
+        // Cached apart from the open documents, which do not get its words.
+        assert!(!backend.docs.contains_key(&uri));
+        assert_eq!(backend.disk_docs[&uri].doc.language_id, "plaintext");
+        assert_eq!(backend.open_words.count("opened"), 0);
+
+        // A change on disk is read again by the next request.
+        fs::write(
+            &path,
+            "lately changed
+lat",
+        )
+        .unwrap();
+        let changed = Notification::new(
+            notification::DidChangeWatchedFiles::METHOD.to_string(),
+            lsp_types::DidChangeWatchedFilesParams {
+                changes: vec![lsp_types::FileEvent::new(
+                    uri.clone(),
+                    lsp_types::FileChangeType::CHANGED,
+                )],
+            },
+        );
+        backend.handle_notification(changed).unwrap();
+        assert!(backend.disk_docs.is_empty());
+        let labels = completion_labels(backend.respond(completion_request(&uri, 1, 3)));
+        assert_eq!(labels, HashSet::from(["lately".to_string()]));
+
+        // So is a change nothing reported, once the modification time moved.
+        fs::write(&path, "latest edit\nlat").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        let labels = completion_labels(backend.respond(completion_request(&uri, 1, 3)));
+        assert_eq!(labels, HashSet::from(["latest".to_string()]));
+
+        // Opening the document replaces the copy from disk.
+        backend
+            .handle_notification(did_open(&uri, "opened"))
+            .unwrap();
+        assert!(backend.disk_docs.is_empty());
+
+        backend.respond(completion_request(&untitled, 0, 0));
+        assert!(!backend.docs.contains_key(&untitled));
+        assert!(!backend.disk_docs.contains_key(&untitled));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_disk_documents_are_bounded() {
+        let dir = std::env::temp_dir().join("lsp-word-test-disk-documents");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        let uris = (0..=MAX_DISK_DOCUMENTS)
+            .map(|i| {
+                let path = dir.join(format!("file{i}.txt"));
+                fs::write(&path, "words").unwrap();
+                format!("file://{}", path.display()).parse::<Uri>().unwrap()
+            })
+            .collect::<Vec<_>>();
+        for uri in &uris {
+            backend.respond(completion_request(uri, 0, 0));
+        }
+        assert_eq!(backend.disk_docs.len(), MAX_DISK_DOCUMENTS);
+        assert!(!backend.disk_docs.contains_key(&uris[0]));
+        assert!(backend.disk_docs.contains_key(&uris[MAX_DISK_DOCUMENTS]));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("getUserName", "get"), Some(0));
+        assert_eq!(fuzzy_score("getUserName", ""), Some(0));
+        assert!(fuzzy_score("getUserName", "gUN").is_some());
+        assert!(fuzzy_score("getUserName", "NUg").is_none());
+        assert!(
+            fuzzy_score("getUserName", "gun").unwrap()
+                < fuzzy_score("gooseUmbrellaNightingale", "gun").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_completion_ranking() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc("getUserName gooseUmbrellaNightingale gooseUmbrellaNightingale gunwale nameUserGet\ngUN"),
+        );
+
+        let config = Config::from_value(Some(serde_json::json!({ "fuzzy": true })));
+        let items = completion_items(
+            create_completion_response(
+                completion_request(&uri, 1, 3),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &config,
+            )
+            .unwrap(),
+        );
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["gunwale", "getUserName", "gooseUmbrellaNightingale"]
+        );
+    }
+
+    #[test]
+    fn test_completion_detail_shows_occurrences() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("apple apple apple avocado\na"));
+
+        let response = create_completion_response(
+            completion_request(&uri, 1, 1),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let details: Vec<(String, Option<String>)> = completion_items(response)
+            .into_iter()
+            .map(|item| (item.label, item.detail))
+            .collect();
+        assert_eq!(
+            details,
+            [
+                ("apple".to_string(), Some("3 occurrences".to_string())),
+                ("avocado".to_string(), Some("1 occurrence".to_string())),
+            ]
+        );
+    }
+
+    fn resolve_request(item: &CompletionItem) -> Request {
+        Request::new(
+            1.into(),
+            request::ResolveCompletionItem::METHOD.to_string(),
+            item,
+        )
+    }
+
+    fn resolved_item(response: Message) -> CompletionItem {
+        match response {
+            Message::Response(resp) => {
+                serde_json::from_value(resp.response_result.unwrap()).unwrap()
+            }
+            _ => panic!("Expected a response"),
+        }
+    }
+
+    #[test]
+    fn test_completion_resolve_attaches_context() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let other = "file:///other".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(other.clone(), doc("let banana = 0;"));
+        docs.insert(uri.clone(), doc("fn main() {\n    let banana = 1;\n}\nb"));
+
+        let items = completion_items(
+            create_completion_response(
+                completion_request(&uri, 3, 1),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        let banana = items.iter().find(|item| item.label == "banana").unwrap();
+        assert_eq!(banana.documentation, None);
+
+        let resolved = resolved_item(
+            create_completion_resolve_response(resolve_request(banana), &docs, &Config::default())
+                .unwrap(),
+        );
+        assert_eq!(
+            resolved.documentation,
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "```\nlet banana = 1;\n```".to_string(),
+            }))
+        );
+        assert_eq!(resolved.label, "banana");
+    }
+
+    #[test]
+    fn test_completion_resolve_context_lines() {
+        let mut docs = HashMap::new();
+        docs.insert(
+            "file:///test".parse::<Uri>().unwrap(),
+            doc("fn main() {\n    let banana = 1;\n    eat(banana);\n}\n\tlet cherry = 2;\n"),
+        );
+        let mut config =
+            Config::from_value(Some(serde_json::json!({ "completionContextLines": 1 })));
+        let context = |label: &str, config: &Config| {
+            let item = CompletionItem {
+                label: label.to_string(),
+                ..Default::default()
+            };
+            let resolved = resolved_item(
+                create_completion_resolve_response(resolve_request(&item), &docs, config).unwrap(),
+            );
+            match resolved.documentation {
+                Some(Documentation::MarkupContent(content)) => content.value,
+                other => panic!("Expected markup documentation, got {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            context("banana", &config),
+            "```\nfn main() {\n    let banana = 1;\n    eat(banana);\n```"
+        );
+        // The first and last lines have nothing on one side.
+        assert_eq!(
+            context("main", &config),
+            "```\nfn main() {\n    let banana = 1;\n```"
+        );
+        assert_eq!(context("cherry", &config), "```\n}\n\tlet cherry = 2;\n```");
+
+        // Only the lines of the document are visited, however many are asked for.
+        config.completion_context_lines = u32::MAX;
+        assert_eq!(
+            context("cherry", &config),
+            "```\nfn main() {\n    let banana = 1;\n    eat(banana);\n}\n\tlet cherry = 2;\n```"
+        );
+    }
+
+    #[test]
+    fn test_completion_resolve_unknown_word_is_unchanged() {
+        let docs = HashMap::new();
+        let item = CompletionItem {
+            label: "missing".to_string(),
+            ..Default::default()
+        };
+        let resolved = resolved_item(
+            create_completion_resolve_response(resolve_request(&item), &docs, &Config::default())
+                .unwrap(),
+        );
+        assert_eq!(resolved, item);
+    }
+
+    #[test]
+    fn test_compound_snippet() {
+        assert_eq!(
+            compound_snippet("user_id_map").as_deref(),
+            Some("user_${1:id}_${2:map}")
+        );
+        assert_eq!(
+            compound_snippet("_private_name").as_deref(),
+            Some("_private_${1:name}")
+        );
+        assert_eq!(compound_snippet("plain"), None);
+        assert_eq!(compound_snippet("__init__"), None);
+    }
+
+    #[test]
+    fn test_snippet_completions_for_compound_words() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("user_id_map username\nu"));
+
+        let request = || completion_request(&uri, 1, 1);
+        let mut snippets = |config: &Config| -> Vec<(String, String, Option<InsertTextFormat>)> {
+            completion_items(
+                create_completion_response(
+                    request(),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    config,
+                )
+                .unwrap(),
+            )
+            .into_iter()
+            .map(|item| {
+                let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else {
+                    panic!("Expected a text edit");
+                };
+                (item.label, edit.new_text, item.insert_text_format)
+            })
+            .collect()
+        };
+
+        let config = Config::from_value(Some(serde_json::json!({ "snippetCompletions": true })));
+        assert_eq!(
+            snippets(&config),
+            [
+                (
+                    "user_id_map".to_string(),
+                    "user_${1:id}_${2:map}".to_string(),
+                    Some(InsertTextFormat::SNIPPET),
+                ),
+                ("username".to_string(), "username".to_string(), None),
+            ]
+        );
+        assert!(
+            snippets(&Config::default())
+                .iter()
+                .all(|(label, text, format)| label == text && format.is_none())
+        );
+    }
+
+    #[test]
+    fn test_initialize_result_has_server_info() {
+        let result = serde_json::to_value(initialize_result(&Config::default())).unwrap();
+        assert_eq!(result["serverInfo"]["name"], "lsp-word");
+        assert_eq!(result["serverInfo"]["version"], env!("CARGO_PKG_VERSION"));
+        assert!(result["capabilities"]["completionProvider"].is_object());
+    }
+
+    #[test]
+    fn test_server_capabilities_trigger_characters() {
+        let triggers = |config: &Config| {
+            server_capabilities(config)
+                .completion_provider
+                .and_then(|options| options.trigger_characters)
+        };
+
+        let defaults = triggers(&Config::default()).unwrap();
+        assert_eq!(defaults.len(), 26);
+        assert!(defaults.contains(&"a".to_string()) && defaults.contains(&"z".to_string()));
+
+        let config = Config::from_value(Some(serde_json::json!({ "triggerCharacters": ["."] })));
+        assert_eq!(triggers(&config), Some(vec![".".to_string()]));
+
+        let config = Config::from_value(Some(serde_json::json!({ "triggerCharacters": [] })));
+        assert_eq!(triggers(&config), Some(vec![]));
+    }
+
+    #[test]
+    fn test_completion_excludes_language_keywords() {
+        let rust = "file:///main.rs".parse::<Uri>().unwrap();
+        let text = "file:///notes.txt".parse::<Uri>().unwrap();
+        let config = Config::from_value(Some(serde_json::json!({
+            "excludeKeywords": true,
+            "allDocuments": false,
+        })));
+        let mut backend = Backend::new(config, Default::default());
+        let source = "fn main() { let value = 1; }\n";
+        for notification in [
+            did_open_language(&rust, "rust", source),
+            did_open_language(&text, "plaintext", source),
+        ] {
+            backend.handle_notification(notification).unwrap();
+        }
+
+        let mut labels =
+            |uri: &Uri| completion_labels(backend.respond(completion_request(uri, 1, 0)));
+        let expected: HashSet<String> = ["main", "value"].map(String::from).into();
+        assert_eq!(labels(&rust), expected);
+        let expected: HashSet<String> = ["fn", "main", "let", "value"].map(String::from).into();
+        assert_eq!(labels(&text), expected);
+    }
+
+    #[test]
+    fn test_completion_excludes_stop_words() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("The cat and the hat of Gold\n"));
+
+        let mut labels = |config: &Config| {
+            completion_labels(
+                create_completion_response(
+                    completion_request(&uri, 1, 0),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    config,
+                )
+                .unwrap(),
+            )
+        };
+        let config = Config::from_value(Some(serde_json::json!({
+            "stopWords": ["the", "AND", "of"],
+        })));
+        let expected: HashSet<String> = ["cat", "hat", "Gold"].map(String::from).into();
+        assert_eq!(labels(&config), expected);
+
+        let config = Config::from_value(Some(serde_json::json!({ "stopWords": [] })));
+        assert_eq!(labels(&config).len(), 7);
+    }
+
+    #[test]
+    fn test_least_recently_used_documents_are_evicted() {
+        let uris: Vec<Uri> = ["file:///a", "file:///b", "file:///c"]
+            .iter()
+            .map(|uri| uri.parse().unwrap())
+            .collect();
+        let config = Config::from_value(Some(serde_json::json!({ "maxCachedDocuments": 2 })));
+        let mut backend = Backend::new(config, Default::default());
+        for (uri, text) in uris.iter().zip(["alpha", "beta", "gamma"]) {
+            backend.handle_notification(did_open(uri, text)).unwrap();
+        }
+        let cached = |docs: &HashMap<Uri, Document>| -> Vec<bool> {
+            uris.iter().map(|uri| !docs[uri].evicted).collect()
+        };
+        assert_eq!(cached(&backend.docs), [false, true, true]);
+        assert!(
+            backend.docs[&uris[0]]
+                .words
+                .prefix_completions("")
+                .is_empty()
+        );
+        assert_eq!(backend.docs[&uris[0]].text, "alpha");
+
+        // Using the evicted document again restores it and evicts the next oldest.
+        backend.focus(&uris[0]);
+        assert_eq!(cached(&backend.docs), [true, false, true]);
+        assert_eq!(
+            backend.docs[&uris[0]].words.prefix_completions(""),
+            ["alpha"]
+        );
+    }
+
+    #[test]
+    fn test_request_document() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        assert_eq!(request_document(&completion_request(&uri, 0, 0)), Some(uri));
+        let req = Request::new(
+            1.into(),
+            request::WorkspaceSymbolRequest::METHOD.to_string(),
+            serde_json::json!({ "query": "" }),
+        );
+        assert_eq!(request_document(&req), None);
+    }
+
+    #[test]
+    fn test_cached_words_seed_completion_until_seen() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("seen seen\n"));
+        let mut workspace = WorkspaceIndex::default();
+        workspace.set_cached_words(WordIndex::from(HashMap::from([
+            ("seen".to_string(), 10),
+            ("remembered".to_string(), 5),
+        ])));
+
+        let response = create_completion_response(
+            completion_request(&uri, 1, 0),
+            &mut docs,
+            &workspace,
+            &WordIndex::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let details: Vec<(String, Option<String>)> = completion_items(response)
+            .into_iter()
+            .map(|item| (item.label, item.detail))
+            .collect();
+        assert_eq!(
+            details,
+            [
+                ("seen".to_string(), Some("2 occurrences".to_string())),
+                ("remembered".to_string(), Some("5 occurrences".to_string())),
+            ]
+        );
+
+        let counts = session_word_counts(&docs, &workspace);
+        assert_eq!(counts["seen"], 2);
+        assert_eq!(counts["remembered"], 5);
+    }
+
+    #[test]
+    fn test_session_word_counts_count_open_files_once() {
+        let root = std::env::temp_dir().join("lsp-word-test-session-counts");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let open = root.join("open.txt");
+        fs::write(&open, "alpha beta").unwrap();
+        fs::write(root.join("closed.txt"), "alpha").unwrap();
+        let workspace = WorkspaceIndex::scan(std::slice::from_ref(&root), &Config::default());
+        let mut docs = HashMap::new();
+        let uri = format!("file://{}", open.display()).parse::<Uri>().unwrap();
+        docs.insert(uri, doc("alpha alpha"));
+
+        // The edited document stands in for its file on disk.
+        let counts = session_word_counts(&docs, &workspace);
+        assert_eq!(counts, HashMap::from([("alpha".to_string(), 3)]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_completion_offers_tags() {
+        let path = std::env::temp_dir().join("lsp-word-test-completion-tags");
+        fs::write(
+            &path,
+            "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/\nparse_header\tsrc/http.rs\t/^fn parse_header($/;\"\tf\n",
+        )
+        .unwrap();
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("parse\npa"));
+
+        let config = Config::from_value(Some(serde_json::json!({ "tagsPath": path })));
+        let response = create_completion_response(
+            completion_request(&uri, 1, 2),
+            &mut docs,
+            &WorkspaceIndex::default(),
+            &WordIndex::default(),
+            &config,
+        )
+        .unwrap();
+        let expected: HashSet<String> = ["parse", "parse_header"].map(String::from).into();
+        assert_eq!(completion_labels(response), expected);
+    }
+
+    #[test]
+    fn test_completion_waits_for_min_prefix_length() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("alpha alps\nal"));
+        let config = Config::from_value(Some(serde_json::json!({ "minPrefixLength": 3 })));
+        let mut complete = |req: Request| {
+            completion_list(
+                create_completion_response(
+                    req,
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    &config,
+                )
+                .unwrap(),
+            )
+        };
+
+        let list = complete(triggered_completion_request(
+            &uri,
+            1,
+            2,
+            CompletionTriggerKind::TRIGGER_CHARACTER,
+        ));
+        assert!(list.items.is_empty());
+        assert!(list.is_incomplete);
+
+        let list = complete(triggered_completion_request(
+            &uri,
+            1,
+            2,
+            CompletionTriggerKind::INVOKED,
+        ));
+        let labels: HashSet<String> = list.items.into_iter().map(|item| item.label).collect();
+        let expected: HashSet<String> = ["alpha", "alps"].map(String::from).into();
+        assert_eq!(labels, expected);
+    }
+
+    #[test]
+    fn test_completion_by_trigger_kind() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("alpha beta apple\n"));
+        let config = Config::from_value(Some(serde_json::json!({ "minPrefixLength": 2 })));
+        let mut complete = |text: &str, trigger_kind| -> Vec<String> {
+            let doc = docs.get_mut(&uri).unwrap();
+            doc.text = format!("alpha beta apple\n{text}");
+            let req = triggered_completion_request(&uri, 1, text.len() as u32, trigger_kind);
+            let list = completion_list(
+                create_completion_response(
+                    req,
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    &config,
+                )
+                .unwrap(),
+            );
+            let mut labels: Vec<String> = list.items.into_iter().map(|item| item.label).collect();
+            labels.sort();
+            labels
+        };
+
+        // A typed trigger character is filtered on, once the prefix is long enough.
+        assert!(complete("a", CompletionTriggerKind::TRIGGER_CHARACTER).is_empty());
+        assert_eq!(
+            complete("ap", CompletionTriggerKind::TRIGGER_CHARACTER),
+            ["apple"]
+        );
+        // Backspace keeps the incomplete list going for the same word.
+        assert_eq!(
+            complete(
+                "a",
+                CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS
+            ),
+            ["alpha", "apple"]
+        );
+        // A new word starts over.
+        assert!(
+            complete(
+                "a b",
+                CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS
+            )
+            .is_empty()
+        );
+        assert_eq!(
+            complete(
+                "a be",
+                CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS
+            ),
+            ["beta"]
+        );
+        // Explicit invocation offers every word, prefix or not.
+        assert_eq!(
+            complete("a b ", CompletionTriggerKind::INVOKED),
+            ["alpha", "apple", "beta"]
+        );
+    }
+
+    #[test]
+    fn test_completion_sort_modes() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("beta Alpha beta gamma beta gamma\n"));
+        let mut sorted = |sort_mode: &str| -> Vec<(String, Option<String>)> {
+            let config = Config::from_value(Some(serde_json::json!({ "sortMode": sort_mode })));
+            let mut items = completion_items(
+                create_completion_response(
+                    completion_request(&uri, 1, 0),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    &config,
+                )
+                .unwrap(),
+            );
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items
+                .into_iter()
+                .map(|item| (item.label, item.sort_text))
+                .collect()
+        };
+        let labels = |items: Vec<(String, Option<String>)>| -> Vec<String> {
+            items.into_iter().map(|(label, _)| label).collect()
+        };
+
+        assert_eq!(labels(sorted("frequency")), ["beta", "gamma", "Alpha"]);
+        assert_eq!(labels(sorted("alphabetical")), ["Alpha", "beta", "gamma"]);
+        assert!(
+            sorted("none")
+                .iter()
+                .all(|(_, sort_text)| sort_text.is_none())
+        );
+    }
+
+    #[test]
+    fn test_completion_ranking_weights() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("beta\n\n\n\n\n\n\nalpha alpha alpha\n"));
+        let mut sorted = |settings: serde_json::Value| -> Vec<String> {
+            let config = Config::from_value(Some(settings));
+            let mut items = completion_items(
+                create_completion_response(
+                    completion_request(&uri, 1, 0),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    &config,
+                )
+                .unwrap(),
+            );
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items.into_iter().map(|item| item.label).collect()
+        };
+
+        // Without weights the nearer word wins, however rare.
+        assert_eq!(sorted(serde_json::json!({})), ["beta", "alpha"]);
+        assert_eq!(
+            sorted(serde_json::json!({ "ranking": {} })),
+            ["alpha", "beta"]
+        );
+        assert_eq!(
+            sorted(serde_json::json!({ "ranking": { "frequency": 0, "proximity": 1 } })),
+            ["beta", "alpha"]
+        );
+        assert_eq!(
+            sorted(serde_json::json!({ "ranking": { "frequency": 1, "proximity": 1 } })),
+            ["alpha", "beta"]
+        );
+    }
+
+    #[test]
+    fn test_completion_merge_casing() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc("color Color color
+co"),
+        );
+        let mut complete = |config: &Config| {
+            completion_items(
+                create_completion_response(
+                    completion_request(&uri, 1, 2),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    config,
+                )
+                .unwrap(),
+            )
+        };
+
+        let labels: HashSet<String> = complete(&Config::default())
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, ["color", "Color"].map(String::from).into());
+
+        let config = Config::from_value(Some(serde_json::json!({ "mergeCasing": true })));
+        let items = complete(&config);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "color");
+        assert_eq!(items[0].detail.as_deref(), Some("3 occurrences"));
+    }
+
+    #[test]
+    fn test_completion_prose_groups_stems() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("ru"));
+        docs.insert(
+            "file:///other".parse::<Uri>().unwrap(),
+            doc("run runs running rust rust"),
+        );
+        let mut sorted = |config: &Config| -> Vec<(String, Option<String>)> {
+            let mut items = completion_items(
+                create_completion_response(
+                    completion_request(&uri, 0, 2),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    config,
+                )
+                .unwrap(),
+            );
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items
+                .into_iter()
+                .map(|item| {
+                    let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else {
+                        panic!("Expected a text edit");
+                    };
+                    (edit.new_text, item.detail)
+                })
+                .collect()
+        };
+        let words = |items: Vec<(String, Option<String>)>| -> Vec<String> {
+            items.into_iter().map(|(word, _)| word).collect()
+        };
+
+        assert_eq!(
+            words(sorted(&Config::default())),
+            ["rust", "run", "running", "runs"]
+        );
+        let config = Config::from_value(Some(serde_json::json!({ "prose": true })));
+        let items = sorted(&config);
+        assert_eq!(items[0], ("run".to_string(), Some(occurrences_label(3))));
+        assert_eq!(words(items), ["run", "running", "runs", "rust"]);
+    }
+
+    #[test]
+    fn test_merge_casing_tie_prefers_lowercase() {
+        let words = HashMap::from([("Color".to_string(), 1), ("color".to_string(), 1)]);
+        assert_eq!(
+            merge_casing(words),
+            HashMap::from([("color".to_string(), 2)])
+        );
+        let words = HashMap::from([("HTTP".to_string(), 1), ("Http".to_string(), 1)]);
+        assert_eq!(
+            merge_casing(words),
+            HashMap::from([("HTTP".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn test_completion_item_defaults() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc("alpha alps user_id
+"),
+        );
+        let mut respond = |config: &Config| {
+            let Message::Response(resp) = create_completion_response(
+                completion_request(&uri, 1, 0),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                config,
+            )
+            .unwrap() else {
+                panic!("Expected a response message");
+            };
+            resp.response_result.unwrap()
+        };
+
+        let list = respond(&Config::default());
+        assert!(list.get("itemDefaults").is_none());
+        assert!(
+            list["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .all(|item| item["data"] == "file:///test")
+        );
+
+        let mut config =
+            Config::from_value(Some(serde_json::json!({ "snippetCompletions": true })));
+        config.completion_item_defaults = vec!["data".to_string(), "insertTextFormat".to_string()];
+        let list = respond(&config);
+        // Only `user_id` is a snippet, so the insert text format is not shared.
+        assert_eq!(
+            list["itemDefaults"],
+            serde_json::json!({ "data": "file:///test" })
+        );
+        let items = list["items"].as_array().unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items.iter().all(|item| item.get("data").is_none()));
+        assert!(
+            items
+                .iter()
+                .any(|item| item.get("insertTextFormat").is_some())
+        );
+
+        // The shared edit range moves too; only the snippet needs its own text.
+        config
+            .completion_item_defaults
+            .push("editRange".to_string());
+        let list = respond(&config);
+        assert_eq!(
+            list["itemDefaults"]["editRange"],
+            serde_json::json!({
+                "start": { "line": 1, "character": 0 },
+                "end": { "line": 1, "character": 0 },
+            })
+        );
+        let items = list["items"].as_array().unwrap();
+        assert!(items.iter().all(|item| item.get("textEdit").is_none()));
+        let texts: HashMap<&str, Option<&str>> = items
+            .iter()
+            .map(|item| {
+                (
+                    item["label"].as_str().unwrap(),
+                    item.get("textEditText").and_then(serde_json::Value::as_str),
+                )
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            HashMap::from([
+                ("alpha", None),
+                ("alps", None),
+                ("user_id", Some("user_${1:id}")),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_completion_text_edit_replaces_prefix() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(
+            uri.clone(),
+            doc("naïve_word
+  x = naï"),
+        );
+        let items = completion_items(
+            create_completion_response(
+                completion_request(&uri, 1, 9),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                Range::new(Position::new(1, 6), Position::new(1, 9)),
+                "naïve_word".to_string(),
+            )))
+        );
+
+        // A position past the end of the line still replaces the prefix from its start.
+        let items = completion_items(
+            create_completion_response(
+                completion_request(&uri, 1, 40),
+                &mut docs,
+                &WorkspaceIndex::default(),
+                &WordIndex::default(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            items[0].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                Range::new(Position::new(1, 6), Position::new(1, 40)),
+                "naïve_word".to_string(),
+            )))
+        );
+    }
+
+    fn execute_command_request(command: &str) -> Request {
+        Request {
+            id: 1.into(),
+            method: request::ExecuteCommand::METHOD.to_string(),
+            params: serde_json::to_value(ExecuteCommandParams {
+                command: command.to_string(),
+                arguments: Vec::new(),
+                work_done_progress_params: Default::default(),
+            })
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_reindex_command() {
+        let root = std::env::temp_dir().join("lsp-word-test-reindex-command");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("one.txt"), "before").unwrap();
+        let config = Config::default();
+        let workspace = WorkspaceIndex::scan(std::slice::from_ref(&root), &config);
+        // Changed outside the editor after the scan.
+        fs::write(root.join("one.txt"), "after").unwrap();
+        fs::write(root.join("two.txt"), "added").unwrap();
+
+        let mut backend = Backend::new(config, Arc::new(Mutex::new(workspace)));
+        let uri = "file:///open".parse::<Uri>().unwrap();
+        backend.docs.insert(uri, doc("open after"));
+        let response = backend
+            .handle_request(execute_command_request(REINDEX_COMMAND))
+            .unwrap();
+        let Some(Message::Response(resp)) = response else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(
+            resp.response_result.unwrap(),
+            serde_json::json!({ "filesScanned": 2, "wordsIndexed": 3 })
+        );
+        assert_eq!(
+            backend
+                .workspace
+                .lock()
+                .unwrap()
+                .words()
+                .prefix_completions(""),
+            ["added", "after"]
+        );
+
+        let response = backend.handle_request(execute_command_request("lsp-word.unknown"));
+        assert!(response.is_err());
+    }
+
+    fn spell_check_config(name: &str) -> Config {
+        let path = std::env::temp_dir().join(format!("lsp-word-test-{name}.txt"));
+        fs::write(&path, "the\nquick\nfox\n").unwrap();
+        Config::from_value(Some(serde_json::json!({
+            "dictionaryPath": path,
+            "spellCheck": true,
+        })))
+    }
+
+    #[test]
+    fn test_spelling_diagnostics() {
+        let mut config = spell_check_config("spelling-diagnostics");
+        let diagnostics = spelling_diagnostics("The quick\n42 brwn fox", "", &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 3), Position::new(1, 7))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(diagnostics[0].message, "Unknown word: brwn");
+
+        config.spell_check = false;
+        assert!(spelling_diagnostics("brwn", "", &config).is_empty());
+        let without_dictionary =
+            Config::from_value(Some(serde_json::json!({ "spellCheck": true })));
+        assert!(spelling_diagnostics("brwn", "", &without_dictionary).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_word_diagnostics() {
+        let config = Config::from_value(Some(serde_json::json!({ "lintDuplicateWords": true })));
+        let diagnostics = duplicate_word_diagnostics("the the cat", "", &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(0, 4), Position::new(0, 7))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+
+        let ranges = |text: &str, language_id: &str| -> Vec<Range> {
+            duplicate_word_diagnostics(text, language_id, &config)
+                .into_iter()
+                .map(|d| d.range)
+                .collect()
+        };
+        assert_eq!(
+            ranges("Ça ça\nis\n  is, is", ""),
+            [
+                Range::new(Position::new(0, 3), Position::new(0, 5)),
+                Range::new(Position::new(2, 2), Position::new(2, 4)),
+            ]
+        );
+        // Code may repeat a name; its comments may not.
+        assert_eq!(
+            ranges("let x = x; y.y // a a", "rust"),
+            [Range::new(Position::new(0, 20), Position::new(0, 21))]
+        );
+        assert!(ranges("42 42 theme the", "").is_empty());
+        let off = Config::default();
+        assert!(duplicate_word_diagnostics("the the", "", &off).is_empty());
+    }
+
+    #[test]
+    fn test_serve_publishes_spelling_diagnostics() {
+        let (server, client) = Connection::memory();
+        let config = spell_check_config("serve-spelling");
+        let handle =
+            std::thread::spawn(move || serve(server, config, Default::default(), None, None));
+
+        let uri = "file:///prose.txt".parse::<Uri>().unwrap();
+        client
+            .sender
+            .send(did_open(&uri, "the quikc fox").into())
+            .unwrap();
+        let Message::Notification(not) = client.receiver.recv().unwrap() else {
+            panic!("Expected a notification");
+        };
+        assert_eq!(not.method, notification::PublishDiagnostics::METHOD);
+        let params = serde_json::from_value::<PublishDiagnosticsParams>(not.params).unwrap();
+        assert_eq!(params.uri, uri);
+        let ranges: Vec<Range> = params.diagnostics.iter().map(|d| d.range).collect();
+        assert_eq!(
+            ranges,
+            [Range::new(Position::new(0, 4), Position::new(0, 9))]
+        );
+
+        // Edits are checked once they settle, with the rebuild.
+        for text in ["the quikc brwn", "the quikc brwn fox"] {
+            client
+                .sender
+                .send(did_change(&uri, vec![full_change(text)]).into())
+                .unwrap();
+        }
+        let Message::Notification(not) = client.receiver.recv().unwrap() else {
+            panic!("Expected a notification");
+        };
+        let params = serde_json::from_value::<PublishDiagnosticsParams>(not.params).unwrap();
+        assert_eq!(params.diagnostics.len(), 2);
+        assert!(client.receiver.try_recv().is_err());
+
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_edit_diagnostics_wait_for_the_edits_to_settle() {
+        let mut backend = Backend::new(spell_check_config("debounced"), Default::default());
+        let uri = "file:///prose.txt".parse::<Uri>().unwrap();
+        backend
+            .handle_notification(did_open(
+                &uri, "the fox
+",
+            ))
+            .unwrap();
+        let change = did_change(&uri, vec![ranged_change((0, 7), (0, 7), " quikc")]);
+        backend.handle_notification(change).unwrap();
+        let method = notification::DidChangeTextDocument::METHOD;
+        assert!(
+            backend
+                .diagnostic_notifications(method, Some(uri.clone()), true)
+                .is_empty()
+        );
+        assert!(backend.due_diagnostics(Instant::now()).is_empty());
+
+        let due = backend.next_reindex().unwrap();
+        let notifications = backend.due_diagnostics(due);
+        assert_eq!(notifications.len(), 1);
+        let params =
+            serde_json::from_value::<PublishDiagnosticsParams>(notifications[0].params.clone())
+                .unwrap();
+        assert_eq!(params.diagnostics.len(), 1);
+        assert_eq!(backend.next_reindex(), None);
+    }
+
+    fn code_action_request(uri: &Uri, range: Range) -> Request {
+        Request {
+            id: 1.into(),
+            method: request::CodeActionRequest::METHOD.to_string(),
+            params: serde_json::to_value(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range,
+                context: Default::default(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap(),
+        }
+    }
+
+    fn code_actions(response: Message) -> Vec<CodeAction> {
+        let Message::Response(resp) = response else {
+            panic!("Expected a response message");
+        };
+        serde_json::from_value::<CodeActionResponse>(resp.response_result.unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action,
+                CodeActionOrCommand::Command(command) => panic!("Unexpected command {command:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_add_to_dictionary_code_action() {
+        let mut backend = Backend::new(spell_check_config("add-to-dictionary"), Default::default());
+        let uri = "file:///prose.txt".parse::<Uri>().unwrap();
+        backend.docs.insert(uri.clone(), doc("the quikc fox"));
+        let at = |character| Range::new(Position::new(0, character), Position::new(0, character));
+
+        let actions = code_actions(
+            backend
+                .handle_request(code_action_request(&uri, at(1)))
+                .unwrap()
+                .unwrap(),
+        );
+        assert!(actions.is_empty());
+
+        let actions = code_actions(
+            backend
+                .handle_request(code_action_request(&uri, at(6)))
+                .unwrap()
+                .unwrap(),
+        );
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].kind, Some(CodeActionKind::QUICKFIX));
+        let command = actions[0].command.clone().unwrap();
+        assert_eq!(command.command, ADD_TO_DICTIONARY_COMMAND);
+
+        let mut req = execute_command_request(&command.command);
+        req.params["arguments"] = command.arguments.unwrap().into();
+        backend.handle_request(req).unwrap();
+        assert!(spelling_diagnostics("the quikc fox", "", &backend.config).is_empty());
+        let path = backend.config.dictionary_path.clone().unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "the\nquick\nfox\nquikc\n"
+        );
+        let actions = code_actions(
+            backend
+                .handle_request(code_action_request(&uri, at(6)))
+                .unwrap()
+                .unwrap(),
+        );
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_workspace_roots() {
+        let folder = |uri: &str| lsp_types::WorkspaceFolder {
+            uri: uri.parse().unwrap(),
+            name: String::new(),
+        };
+        let mut params = InitializeParams {
+            root_uri: Some("file:///legacy".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(workspace_roots(&params), [PathBuf::from("/legacy")]);
+
+        params.workspace_folders = Some(vec![folder("file:///one"), folder("file:///two")]);
+        assert_eq!(
+            workspace_roots(&params),
+            [PathBuf::from("/one"), PathBuf::from("/two")]
+        );
+    }
+
+    #[test]
+    fn test_did_change_workspace_folders() {
+        let root = std::env::temp_dir().join("lsp-word-test-workspace-folders");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("notes.txt"), "folder_only shared_word").unwrap();
+        let folder = lsp_types::WorkspaceFolder {
+            uri: format!("file://{}", root.display()).parse().unwrap(),
+            name: "notes".to_string(),
+        };
+        let change_folders = |added: Vec<_>, removed: Vec<_>| {
+            Notification::new(
+                notification::DidChangeWorkspaceFolders::METHOD.to_string(),
+                lsp_types::DidChangeWorkspaceFoldersParams {
+                    event: lsp_types::WorkspaceFoldersChangeEvent { added, removed },
+                },
+            )
+        };
+
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        backend
+            .handle_notification(did_open(&uri, "shared_word\n"))
+            .unwrap();
+        let labels = |backend: &mut Backend| {
+            completion_labels(backend.respond(completion_request(&uri, 1, 0)))
+        };
+
+        backend
+            .handle_notification(change_folders(vec![folder.clone()], Vec::new()))
+            .unwrap();
+        let expected: HashSet<String> = ["folder_only", "shared_word"].map(String::from).into();
+        assert_eq!(labels(&mut backend), expected);
+
+        backend
+            .handle_notification(change_folders(Vec::new(), vec![folder]))
+            .unwrap();
+        let expected: HashSet<String> = ["shared_word"].map(String::from).into();
+        assert_eq!(labels(&mut backend), expected);
+    }
+
+    #[test]
+    fn test_did_change_watched_files() {
+        let root = std::env::temp_dir().join("lsp-word-test-watched-files");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("watched.txt");
+        fs::write(&path, "stale").unwrap();
+        let config = Config::default();
+        let workspace = WorkspaceIndex::scan(std::slice::from_ref(&root), &config);
+        let mut backend = Backend::new(config, Arc::new(Mutex::new(workspace)));
+
+        fs::write(&path, "fresh").unwrap();
+        let changed = Notification::new(
+            notification::DidChangeWatchedFiles::METHOD.to_string(),
+            lsp_types::DidChangeWatchedFilesParams {
+                changes: vec![lsp_types::FileEvent::new(
+                    format!("file://{}", path.display()).parse().unwrap(),
+                    lsp_types::FileChangeType::CHANGED,
+                )],
+            },
+        );
+        backend.handle_notification(changed).unwrap();
+        assert_eq!(
+            backend
+                .workspace
+                .lock()
+                .unwrap()
+                .words()
+                .prefix_completions(""),
+            ["fresh"]
+        );
+    }
+
+    #[test]
+    fn test_watch_files_registration() {
+        let req = watch_files_registration();
+        assert_eq!(req.method, request::RegisterCapability::METHOD);
+        let params = serde_json::from_value::<RegistrationParams>(req.params).unwrap();
+        assert_eq!(
+            params.registrations[0].method,
+            notification::DidChangeWatchedFiles::METHOD
+        );
+    }
+
+    #[test]
+    fn test_open_word_counts_follow_documents() {
+        let first = "file:///first".parse::<Uri>().unwrap();
+        let second = "file:///second".parse::<Uri>().unwrap();
+        let mut backend = Backend::new(Config::default(), Default::default());
+        for (uri, text) in [(&first, "alpha alpha beta"), (&second, "alpha gamma")] {
+            backend.handle_notification(did_open(uri, text)).unwrap();
+        }
+        let counts = |backend: &Backend| -> Vec<(String, usize)> {
+            backend
+                .open_words
+                .iter()
+                .map(|(word, count)| (word.to_string(), count))
+                .collect()
+        };
+        assert_eq!(
+            counts(&backend),
+            [("alpha".into(), 3), ("beta".into(), 1), ("gamma".into(), 1)]
+        );
+
+        backend
+            .handle_notification(did_change(&first, vec![full_change("alpha beta beta")]))
+            .unwrap();
+        backend.settle();
+        assert_eq!(
+            counts(&backend),
+            [("alpha".into(), 2), ("beta".into(), 2), ("gamma".into(), 1)]
+        );
+
+        let close = Notification::new(
+            notification::DidCloseTextDocument::METHOD.to_string(),
+            lsp_types::DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: first },
+            },
+        );
+        backend.handle_notification(close).unwrap();
+        assert_eq!(counts(&backend), [("alpha".into(), 1), ("gamma".into(), 1)]);
+    }
+
+    #[test]
+    fn test_completion_ranks_by_open_document_frequency() {
+        let current = "file:///current".parse::<Uri>().unwrap();
+        let other = "file:///other".parse::<Uri>().unwrap();
+        let config = Config::from_value(Some(serde_json::json!({ "allDocuments": false })));
+        let mut backend = Backend::new(config, Default::default());
+        for (uri, text) in [(&current, "apple avocado\n"), (&other, "avocado avocado")] {
+            backend.handle_notification(did_open(uri, text)).unwrap();
+        }
+        let items = completion_items(backend.respond(completion_request(&current, 1, 0)));
+        let labels: Vec<String> = items.into_iter().map(|item| item.label).collect();
+        assert_eq!(labels, ["avocado", "apple"]);
+    }
+
+    #[test]
+    fn test_completion_counts_open_documents_once() {
+        let root = std::env::temp_dir().join("lsp-word-test-open-counted-once");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("notes.txt"), "zucchini zucchini zucchini").unwrap();
+        let config = Config::from_value(Some(serde_json::json!({ "ranking": { "frequency": 1 } })));
+        let workspace = WorkspaceIndex::scan(std::slice::from_ref(&root), &config);
+        let mut backend = Backend::new(config, Arc::new(Mutex::new(workspace)));
+        let uri = "file:///current".parse::<Uri>().unwrap();
+        backend
+            .handle_notification(did_open(&uri, "zest zest\n"))
+            .unwrap();
+
+        // `allDocuments` already counts "zest" twice; the open words must not double that.
+        let items = completion_items(backend.respond(completion_request(&uri, 1, 0)));
+        let labels: Vec<String> = items.into_iter().map(|item| item.label).collect();
+        assert_eq!(labels, ["zucchini", "zest"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_completion_commit_characters() {
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let mut docs = HashMap::new();
+        docs.insert(uri.clone(), doc("alpha beta\n"));
+        let mut commit_characters = |config: &Config| -> Vec<Option<Vec<String>>> {
+            completion_items(
+                create_completion_response(
+                    completion_request(&uri, 1, 0),
+                    &mut docs,
+                    &WorkspaceIndex::default(),
+                    &WordIndex::default(),
+                    config,
+                )
+                .unwrap(),
+            )
+            .into_iter()
+            .map(|item| item.commit_characters)
+            .collect()
+        };
+
+        let mut config =
+            Config::from_value(Some(serde_json::json!({ "commitCharacters": [".", "("] })));
+        // Not sent to clients that did not advertise support.
+        assert_eq!(commit_characters(&config), [None, None]);
+
+        config.commit_characters_support = true;
+        let expected = Some(vec![".".to_string(), "(".to_string()]);
+        assert_eq!(commit_characters(&config), [expected.clone(), expected]);
+
+        config.update(serde_json::json!({ "commitCharacters": [] }));
+        assert_eq!(commit_characters(&config), [None, None]);
+    }
+
+    #[test]
+    fn test_comments_only_completion() {
+        let uri = "file:///lib.rs".parse::<Uri>().unwrap();
+        let text =
+            "// Parses the manifest\nfn make_manifest() -> &str { \"missing manifest\" }\nma";
+        let config = Config::from_value(Some(serde_json::json!({ "commentsOnly": ["rust"] })));
+        let mut backend = Backend::new(config, Default::default());
+        backend
+            .handle_notification(did_open_language(&uri, "rust", text))
+            .unwrap();
+        let labels = completion_labels(backend.respond(completion_request(&uri, 2, 2)));
+        assert_eq!(labels, ["manifest"].map(String::from).into());
+
+        backend
+            .handle_notification(did_open_language(&uri, "plaintext", text))
+            .unwrap();
+        let labels = completion_labels(backend.respond(completion_request(&uri, 2, 2)));
+        assert_eq!(
+            labels,
+            ["make_manifest", "manifest"].map(String::from).into()
+        );
+    }
+
+    #[test]
+    fn test_stats_request() {
+        let root = std::env::temp_dir().join("lsp-word-test-stats");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("file.txt"), "shared disk_only").unwrap();
+        let config = Config::default();
+        let workspace = WorkspaceIndex::scan(std::slice::from_ref(&root), &config);
+        let mut backend = Backend::new(config, Arc::new(Mutex::new(workspace)));
+        backend
+            .docs
+            .insert("file:///a".parse::<Uri>().unwrap(), doc("shared alpha"));
+        backend
+            .docs
+            .insert("file:///b".parse::<Uri>().unwrap(), doc("alpha beta"));
+
+        let req = Request::new(1.into(), STATS_METHOD.to_string(), ());
+        let Message::Response(resp) = backend.respond(req) else {
+            panic!("Expected a response message");
+        };
+        let stats = resp.response_result.unwrap();
+        assert_eq!(stats["documents"], 2);
+        assert_eq!(stats["workspaceFiles"], 1);
+        assert_eq!(stats["uniqueWords"], 4);
+        assert!(stats["memoryBytes"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_tokenize_request() {
+        let config = Config::from_value(Some(serde_json::json!({
+            "allowLeadingDigits": true,
+            "excludeNumbers": true,
+            "minWordLength": 3,
+            "excludeKeywords": true,
+            "stopWords": ["THE"],
+            "splitIdentifiers": true,
+        })));
+        let mut backend = Backend::new(config, Default::default());
+        let uri = "file:///test.rs".parse::<Uri>().unwrap();
+        let mut document = doc("match the_value 42 ab Hello");
+        document.language_id = "rust".to_string();
+        backend.docs.insert(uri.clone(), document);
+
+        let expected = serde_json::json!({
+            "languageId": "rust",
+            "tokens": [
+                { "word": "match", "dropped": "keyword" },
+                { "word": "the_value", "dropped": null },
+                { "word": "the", "dropped": "stopWord" },
+                { "word": "value", "dropped": null },
+                { "word": "42", "dropped": "number" },
+                { "word": "ab", "dropped": "tooShort" },
+                { "word": "Hello", "dropped": null },
+            ],
+        });
+        let params = serde_json::json!({ "textDocument": { "uri": uri } });
+        let req = Request::new(1.into(), TOKENIZE_METHOD.to_string(), params);
+        let Message::Response(resp) = backend.respond(req) else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(resp.response_result.unwrap(), expected);
+
+        // Raw text is read as plain text unless a language is given.
+        let params = serde_json::json!({ "text": "match ab" });
+        let req = Request::new(2.into(), TOKENIZE_METHOD.to_string(), params);
+        let Message::Response(resp) = backend.respond(req) else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(
+            resp.response_result.unwrap()["tokens"],
+            serde_json::json!([
+                { "word": "match", "dropped": null },
+                { "word": "ab", "dropped": "tooShort" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_will_save_wait_until_trims_trailing_whitespace() {
+        let config = Config::from_value(Some(
+            serde_json::json!({ "trimTrailingWhitespaceOnSave": true }),
+        ));
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let edits = |backend: &mut Backend, text: &str| -> Vec<TextEdit> {
+            backend.docs.insert(uri.clone(), doc(text));
+            let params = lsp_types::WillSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                reason: lsp_types::TextDocumentSaveReason::MANUAL,
+            };
+            let req = Request::new(1.into(), request::WillSaveWaitUntil::METHOD.into(), params);
+            let Message::Response(resp) = backend.respond(req) else {
+                panic!("Expected a response message");
+            };
+            serde_json::from_value(resp.response_result.unwrap()).unwrap()
+        };
+        let delete = |line, from, to| {
+            TextEdit::new(
+                Range::new(Position::new(line, from), Position::new(line, to)),
+                String::new(),
+            )
+        };
+
+        let mut backend = Backend::new(config, Default::default());
+        assert_eq!(
+            edits(&mut backend, "alpha  \r\nbeta\t\n  \ngamma "),
+            [
+                delete(0, 5, 7),
+                delete(1, 4, 5),
+                delete(2, 0, 2),
+                delete(3, 5, 6)
+            ]
+        );
+        assert!(edits(&mut backend, "alpha\r\nbeta\n\ngamma").is_empty());
+        // A lone `\r` ends a line too.
+        assert_eq!(edits(&mut backend, "a \rb"), [delete(0, 1, 2)]);
+
+        let mut backend = Backend::new(Config::default(), Default::default());
+        assert!(edits(&mut backend, "alpha  ").is_empty());
+    }
+
+    #[test]
+    fn test_server_capabilities_experimental() {
+        let capabilities = server_capabilities(&Config::default());
+        assert_eq!(
+            capabilities.experimental,
+            Some(serde_json::json!({
+                "lspWord": { "version": 1, "statsRequest": "lsp-word/stats" }
+            }))
+        );
+    }
+
+    #[test]
+    fn test_serve_ignores_empty_change() {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || {
+            serve(server, Config::default(), Default::default(), None, None)
+        });
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        client.sender.send(did_open(&uri, "alpha").into()).unwrap();
+        client
+            .sender
+            .send(did_change(&uri, Vec::new()).into())
+            .unwrap();
+        let unopened = "file:///unopened".parse::<Uri>().unwrap();
+        client
+            .sender
+            .send(did_change(&unopened, Vec::new()).into())
+            .unwrap();
+
+        let req = Request::new(1.into(), STATS_METHOD.to_string(), ());
+        client.sender.send(req.into()).unwrap();
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        assert_eq!(resp.response_result.unwrap()["documents"], 1);
+        client
+            .sender
+            .send(completion_request(&uri, 0, 0).into())
+            .unwrap();
+        let Message::Response(resp) = client.receiver.recv().unwrap() else {
+            panic!("Expected a response message");
+        };
+        let list = serde_json::from_value::<CompletionList>(resp.response_result.unwrap()).unwrap();
+        let labels: Vec<String> = list.items.into_iter().map(|item| item.label).collect();
+        assert_eq!(labels, ["alpha"]);
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_serve_registers_file_watching_when_initialized() {
+        let initialized: Message = Notification::new(
+            notification::Initialized::METHOD.to_string(),
+            lsp_types::InitializedParams {},
+        )
+        .into();
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        for watch_files_support in [true, false] {
+            let (server, client) = Connection::memory();
+            let config = Config {
+                watch_files_support,
+                ..Config::default()
+            };
+            let handle =
+                std::thread::spawn(move || serve(server, config, Default::default(), None, None));
+            client.sender.send(initialized.clone()).unwrap();
+            if watch_files_support {
+                let Message::Request(req) = client.receiver.recv().unwrap() else {
+                    panic!("Expected a request");
+                };
+                assert_eq!(req.method, request::RegisterCapability::METHOD);
+                let params = serde_json::from_value::<RegistrationParams>(req.params).unwrap();
+                assert_eq!(
+                    params.registrations[0].method,
+                    notification::DidChangeWatchedFiles::METHOD
+                );
+                // The client's answer is ignored.
+                client
+                    .sender
+                    .send(Response::new_ok(req.id, ()).into())
+                    .unwrap();
+            }
+            client
+                .sender
+                .send(completion_request(&uri, 0, 0).into())
+                .unwrap();
+            assert!(matches!(
+                client.receiver.recv().unwrap(),
+                Message::Response(_)
+            ));
+            drop(client);
+            handle.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_serve_pulls_configuration_when_initialized() {
+        let (server, client) = Connection::memory();
+        let config = Config {
+            configuration_support: true,
+            ..Config::default()
+        };
+        let handle =
+            std::thread::spawn(move || serve(server, config, Default::default(), None, None));
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        client
+            .sender
+            .send(did_open(&uri, "ab abc\n").into())
+            .unwrap();
+        client
+            .sender
+            .send(
+                Notification::new(
+                    notification::Initialized::METHOD.to_string(),
+                    lsp_types::InitializedParams {},
+                )
+                .into(),
+            )
+            .unwrap();
+
+        let Message::Request(req) = client.receiver.recv().unwrap() else {
+            panic!("Expected a request");
+        };
+        assert_eq!(req.method, request::WorkspaceConfiguration::METHOD);
+        let params = serde_json::from_value::<lsp_types::ConfigurationParams>(req.params).unwrap();
+        assert_eq!(params.items[0].section.as_deref(), Some("lsp-word"));
+        client
+            .sender
+            .send(Response::new_ok(req.id, [serde_json::json!({ "minWordLength": 3 })]).into())
+            .unwrap();
+
+        client
+            .sender
+            .send(completion_request(&uri, 1, 0).into())
+            .unwrap();
+        let expected: HashSet<String> = ["abc"].map(String::from).into();
+        assert_eq!(completion_labels(client.receiver.recv().unwrap()), expected);
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_serve_shows_settings_problems() {
+        let (server, client) = Connection::memory();
+        let config = Config::from_value(Some(serde_json::json!({ "tagsPath": "/nonexistent" })));
+        let handle =
+            std::thread::spawn(move || serve(server, config, Default::default(), None, None));
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let warning = |message: Message| {
+            let Message::Notification(not) = message else {
+                panic!("Expected a notification");
+            };
+            assert_eq!(not.method, notification::ShowMessage::METHOD);
+            let params = serde_json::from_value::<ShowMessageParams>(not.params).unwrap();
+            assert_eq!(params.typ, MessageType::WARNING);
+            params.message
+        };
+        let bad_pattern: Message = Notification::new(
+            notification::DidChangeConfiguration::METHOD.to_string(),
+            lsp_types::DidChangeConfigurationParams {
+                settings: serde_json::json!({ "wordPattern": "[a-" }),
+            },
+        )
+        .into();
+
+        assert!(warning(client.receiver.recv().unwrap()).contains("Ignoring tags file"));
+        client.sender.send(bad_pattern.clone()).unwrap();
+        assert!(
+            warning(client.receiver.recv().unwrap())
+                .starts_with("lsp-word: Invalid wordPattern \"[a-\"")
+        );
+        // The same problem again is only logged.
+        client.sender.send(bad_pattern).unwrap();
+        client
+            .sender
+            .send(completion_request(&uri, 0, 0).into())
+            .unwrap();
+        assert!(matches!(
+            client.receiver.recv().unwrap(),
+            Message::Response(_)
+        ));
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_settings_problems_repeat_after_interval() {
+        let mut backend = Backend::new(Config::default(), Default::default());
+        let now = Instant::now();
+        backend
+            .config
+            .update(serde_json::json!({ "logLevel": "loud" }));
+        assert_eq!(backend.problem_notifications(now).len(), 1);
+        backend
+            .config
+            .update(serde_json::json!({ "logLevel": "loud" }));
+        assert!(backend.problem_notifications(now).is_empty());
+        backend
+            .config
+            .update(serde_json::json!({ "logLevel": "loud" }));
+        assert_eq!(
+            backend
+                .problem_notifications(now + PROBLEM_REPEAT_INTERVAL)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_serve_log_trace() {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || {
+            serve(server, Config::default(), Default::default(), None, None)
+        });
+        let uri = "file:///test".parse::<Uri>().unwrap();
+        let set_trace = |value| -> Message {
+            Notification::new(
+                notification::SetTrace::METHOD.to_string(),
+                SetTraceParams { value },
+            )
+            .into()
+        };
+
+        client.sender.send(did_open(&uri, "alpha").into()).unwrap();
+        client
+            .sender
+            .send(completion_request(&uri, 0, 0).into())
+            .unwrap();
+        assert!(matches!(
+            client.receiver.recv().unwrap(),
+            Message::Response(_)
+        ));
+
+        client.sender.send(set_trace(TraceValue::Verbose)).unwrap();
+        client
+            .sender
+            .send(completion_request(&uri, 0, 0).into())
+            .unwrap();
+        assert!(matches!(
+            client.receiver.recv().unwrap(),
+            Message::Response(_)
+        ));
+        let Message::Notification(not) = client.receiver.recv().unwrap() else {
+            panic!("Expected a notification");
+        };
+        assert_eq!(not.method, notification::LogTrace::METHOD);
+        let params = serde_json::from_value::<LogTraceParams>(not.params).unwrap();
+        assert!(
+            params
+                .message
+                .starts_with("Handled textDocument/completion (1)")
+        );
+        assert!(params.verbose.is_some());
+
+        client.sender.send(set_trace(TraceValue::Off)).unwrap();
+        client
+            .sender
+            .send(completion_request(&uri, 0, 0).into())
+            .unwrap();
+        assert!(matches!(
+            client.receiver.recv().unwrap(),
+            Message::Response(_)
+        ));
+        drop(client);
+        handle.join().unwrap().unwrap();
+    }
 }
@@ -0,0 +1,229 @@
+use crossbeam_channel::Sender;
+use lsp_server::{Message, Notification, Request, Response};
+use lsp_types::notification::{self, Notification as _};
+use lsp_types::request::{self, Request as _};
+use lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Server-initiated `$/progress` reporting, only to be used when the client
+/// advertised `window.workDoneProgress`. Clones share the same token, so that the
+/// worker reporting and the main loop receiving the create response can both hold one.
+#[derive(Clone)]
+pub struct Progress {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    sender: Sender<Message>,
+    token: NumberOrString,
+    title: String,
+    /// Last percentage sent, so that reports are only sent when it changes.
+    percentage: AtomicU32,
+    /// Held while notifying, so that notifications go out in order.
+    state: Mutex<State>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /// The create request is unanswered, so the token may not be used yet.
+    Requested {
+        ended: bool,
+    },
+    Begun,
+    /// The client refused the token, or the work ended; nothing more is sent.
+    Done,
+}
+
+impl Progress {
+    /// Asks the client to create a progress token. The start of the work is announced once
+    /// [`Progress::created`] receives the client's answer.
+    pub fn begin(sender: Sender<Message>, token: &str, title: &str) -> Self {
+        let progress = Progress {
+            shared: Arc::new(Shared {
+                sender,
+                token: NumberOrString::String(token.to_string()),
+                title: title.to_string(),
+                percentage: AtomicU32::new(0),
+                state: Mutex::new(State::Requested { ended: false }),
+            }),
+        };
+        progress.send(Message::Request(Request::new(
+            token.to_string().into(),
+            request::WorkDoneProgressCreate::METHOD.to_string(),
+            WorkDoneProgressCreateParams {
+                token: progress.shared.token.clone(),
+            },
+        )));
+        progress
+    }
+
+    /// Handles the answer to the create request, returning whether `resp` was that answer.
+    /// Success sends the Begin with the progress made so far; an error drops the progress.
+    /// Work that already ended is not announced at all.
+    pub fn created(&self, resp: &Response) -> bool {
+        let NumberOrString::String(token) = &self.shared.token else {
+            return false;
+        };
+        if resp.id != token.clone().into() {
+            return false;
+        }
+        let mut state = self.shared.state.lock().unwrap();
+        *state = match (*state, &resp.response_result) {
+            (State::Requested { ended: false }, Ok(_)) => {
+                self.notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: self.shared.title.clone(),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(self.shared.percentage.load(Ordering::Relaxed)),
+                }));
+                State::Begun
+            }
+            _ => State::Done,
+        };
+        true
+    }
+
+    pub fn report(&self, done: usize, total: usize) {
+        let percentage = (done * 100).checked_div(total).unwrap_or(100) as u32;
+        let state = self.shared.state.lock().unwrap();
+        if self.shared.percentage.swap(percentage, Ordering::Relaxed) == percentage
+            || *state != State::Begun
+        {
+            return;
+        }
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(format!("{done}/{total}")),
+            percentage: Some(percentage),
+        }));
+    }
+
+    pub fn end(self) {
+        let mut state = self.shared.state.lock().unwrap();
+        *state = match *state {
+            State::Requested { .. } => State::Requested { ended: true },
+            State::Begun => {
+                self.notify(WorkDoneProgress::End(WorkDoneProgressEnd { message: None }));
+                State::Done
+            }
+            State::Done => State::Done,
+        };
+    }
+
+    fn notify(&self, value: WorkDoneProgress) {
+        self.send(Message::Notification(Notification::new(
+            notification::Progress::METHOD.to_string(),
+            ProgressParams {
+                token: self.shared.token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            },
+        )));
+    }
+
+    fn send(&self, message: Message) {
+        // The client going away is noticed by the main loop; progress is best effort.
+        let _ = self.shared.sender.send(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress_values(receiver: &crossbeam_channel::Receiver<Message>) -> Vec<WorkDoneProgress> {
+        receiver
+            .try_iter()
+            .filter_map(|message| match message {
+                Message::Notification(not) => {
+                    let params = serde_json::from_value::<ProgressParams>(not.params).unwrap();
+                    let ProgressParamsValue::WorkDone(value) = params.value;
+                    Some(value)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reports_only_changed_percentages() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let progress = Progress::begin(sender, "test", "Testing");
+        match receiver.try_recv().unwrap() {
+            Message::Request(req) => {
+                assert_eq!(req.method, request::WorkDoneProgressCreate::METHOD)
+            }
+            message => panic!("Expected a create request, got {message:?}"),
+        }
+        assert!(progress.created(&Response::new_ok("test".to_string().into(), ())));
+        for done in 1..=4 {
+            progress.report(done, 400);
+        }
+        progress.report(4, 4);
+        progress.end();
+
+        let values = progress_values(&receiver);
+        assert!(matches!(values[0], WorkDoneProgress::Begin(_)));
+        assert!(matches!(
+            values[1],
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                percentage: Some(1),
+                ..
+            })
+        ));
+        assert!(matches!(
+            values[2],
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                percentage: Some(100),
+                ..
+            })
+        ));
+        assert!(matches!(values[3], WorkDoneProgress::End(_)));
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn test_begins_only_once_created() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let progress = Progress::begin(sender, "test", "Testing");
+        progress.report(1, 2);
+        assert!(progress_values(&receiver).is_empty());
+
+        assert!(!progress.created(&Response::new_ok("other".to_string().into(), ())));
+        assert!(progress.created(&Response::new_ok("test".to_string().into(), ())));
+        progress.end();
+        let values = progress_values(&receiver);
+        assert!(matches!(
+            values[0],
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                percentage: Some(50),
+                ..
+            })
+        ));
+        assert!(matches!(values[1], WorkDoneProgress::End(_)));
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn test_refused_or_finished_progress_is_not_sent() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let progress = Progress::begin(sender.clone(), "test", "Testing");
+        let refused = Response::new_err(
+            "test".to_string().into(),
+            lsp_server::ErrorCode::InternalError as i32,
+            "No progress".to_string(),
+        );
+        assert!(progress.created(&refused));
+        progress.report(1, 2);
+        progress.end();
+        assert!(progress_values(&receiver).is_empty());
+
+        let progress = Progress::begin(sender, "test", "Testing");
+        progress.clone().end();
+        assert!(progress.created(&Response::new_ok("test".to_string().into(), ())));
+        assert!(progress_values(&receiver).is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+/// English suffixes removed by [`stem`], tried in this order; `ies` becomes `y`.
+const SUFFIXES: [&str; 5] = ["ies", "ing", "ed", "es", "s"];
+
+/// Stems shorter than this keep their suffix, so that `sing` and `bus` stay whole.
+const MIN_STEM_CHARS: usize = 3;
+
+/// The lowercased `word` without its first matching suffix from `SUFFIXES`: `es` only after
+/// `s`, `x`, `z`, `ch` or `sh`, `s` not after another `s`, and the doubled consonant that
+/// `ing` and `ed` may add is undone, as in `running`. Deliberately light: irregular forms
+/// such as `ran` are not related.
+pub fn stem(word: &str) -> String {
+    let word = word.to_lowercase();
+    for suffix in SUFFIXES {
+        let Some(base) = word.strip_suffix(suffix) else {
+            continue;
+        };
+        if base.chars().count() < MIN_STEM_CHARS {
+            continue;
+        }
+        match suffix {
+            "ies" => return format!("{base}y"),
+            "es" if !["s", "x", "z", "ch", "sh"]
+                .iter()
+                .any(|end| base.ends_with(end)) =>
+            {
+                continue;
+            }
+            "s" if base.ends_with('s') => continue,
+            "ing" | "ed" => return undouble(base).to_owned(),
+            _ => return base.to_owned(),
+        }
+    }
+    word
+}
+
+/// `base` without the last of two equal final consonants, except `l`, `s` and `z`, which are
+/// often doubled in the word itself, as in `falling` or `missed`.
+fn undouble(base: &str) -> &str {
+    let mut chars = base.chars().rev();
+    match (chars.next(), chars.next()) {
+        (Some(last), Some(before))
+            if last == before && last.is_alphabetic() && !"aeioulsz".contains(last) =>
+        {
+            &base[..base.len() - last.len_utf8()]
+        }
+        _ => base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stem() {
+        for word in ["run", "runs", "running", "Runs"] {
+            assert_eq!(stem(word), "run", "{word}");
+        }
+        assert_eq!(stem("watches"), "watch");
+        assert_eq!(stem("makes"), "make");
+        assert_eq!(stem("stories"), "story");
+        assert_eq!(stem("falling"), "fall");
+        assert_eq!(stem("missed"), "miss");
+        assert_eq!(stem("class"), "class");
+        assert_eq!(stem("sing"), "sing");
+        assert_eq!(stem("bus"), "bus");
+    }
+}
@@ -0,0 +1,45 @@
+use crate::index::WordIndex;
+use std::collections::HashMap;
+use std::{fs, io, path::Path};
+
+/// Loads the tag names of a ctags `tags` file, whose lines are `name<TAB>file<TAB>pattern...`.
+/// Lines starting with `!` are metadata and skipped. Like dictionary words, tags carry no
+/// occurrences of their own.
+pub fn load_tags(path: &Path) -> io::Result<WordIndex> {
+    let content = fs::read_to_string(path)?;
+    Ok(WordIndex::from(
+        content
+            .lines()
+            .filter(|line| !line.starts_with('!'))
+            .filter_map(|line| line.split('\t').next())
+            .filter(|name| !name.is_empty())
+            .map(|name| (name.to_owned(), 0))
+            .collect::<HashMap<_, _>>(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_tags() {
+        let path = std::env::temp_dir().join("lsp-word-test-load-tags");
+        fs::write(
+            &path,
+            "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+             WordIndex\tsrc/index.rs\t/^pub struct WordIndex {$/;\"\ts\n\
+             load_tags\tsrc/tags.rs\t/^pub fn load_tags(path: &Path)$/;\"\tf\n\
+             WordIndex\tsrc/index.rs\t/^impl WordIndex {$/;\"\ti\n",
+        )
+        .unwrap();
+        let tags = load_tags(&path).unwrap();
+        assert_eq!(tags.prefix_completions(""), ["WordIndex", "load_tags"]);
+    }
+
+    #[test]
+    fn test_load_missing_tags() {
+        let path = std::env::temp_dir().join("lsp-word-test-missing-tags");
+        assert!(load_tags(&path).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use lsp_types::{Position, PositionEncodingKind, Range, TextDocumentContentChangeEvent};
+
+/// What the `character` of an LSP position counts, negotiated with the client at initialization.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Bytes of UTF-8.
+    Utf8,
+    /// UTF-16 code units, which every client supports.
+    #[default]
+    Utf16,
+}
+
+impl Encoding {
+    /// Prefers UTF-8, which needs no conversion, when the client offers it.
+    pub fn negotiate(offered: &[PositionEncodingKind]) -> Self {
+        if offered.contains(&PositionEncodingKind::UTF8) {
+            Encoding::Utf8
+        } else {
+            Encoding::Utf16
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Encoding::Utf8 => PositionEncodingKind::UTF8,
+            Encoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    /// The length of `text` in position units.
+    pub fn len(self, text: &str) -> usize {
+        match self {
+            Encoding::Utf8 => text.len(),
+            Encoding::Utf16 => text.encode_utf16().count(),
+        }
+    }
+}
+
+/// Byte offsets at which each line starts. As in LSP, `\r\n`, `\n` and a lone `\r`
+/// all end a line.
+fn line_starts(content: &str) -> Vec<usize> {
+    let bytes = content.as_bytes();
+    let mut starts = vec![0];
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'\n' => starts.push(i + 1),
+            b'\r' if bytes.get(i + 1) != Some(&b'\n') => starts.push(i + 1),
+            _ => (),
+        }
+    }
+    starts
+}
+
+/// The text of the line starting at `start`, without its terminator.
+fn line_at(content: &str, start: usize) -> &str {
+    let rest = &content[start..];
+    &rest[..rest.find(['\r', '\n']).unwrap_or(rest.len())]
+}
+
+/// Converts an LSP position (line, `encoding` unit) into a byte offset into `content`.
+/// Positions past the end of a line or inside a character clamp to the line end or the
+/// character's end, and lines past the end of the document clamp to the document end.
+pub fn position_to_offset(content: &str, position: Position, encoding: Encoding) -> usize {
+    let Some(&line_start) = line_starts(content).get(position.line as usize) else {
+        return content.len();
+    };
+    let line = line_at(content, line_start);
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += match encoding {
+            Encoding::Utf8 => c.len_utf8(),
+            Encoding::Utf16 => c.len_utf16(),
+        };
+    }
+    line_start + line.len()
+}
+
+/// Maps byte offsets back to LSP positions. Line starts are computed once so
+/// that converting many offsets in the same document stays cheap.
+pub struct LineIndex<'a> {
+    content: &'a str,
+    line_starts: Vec<usize>,
+    encoding: Encoding,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(content: &'a str, encoding: Encoding) -> Self {
+        LineIndex {
+            content,
+            line_starts: line_starts(content),
+            encoding,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self
+            .encoding
+            .len(&self.content[self.line_starts[line]..offset]);
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, range: std::ops::Range<usize>) -> Range {
+        Range::new(self.position(range.start), self.position(range.end))
+    }
+
+    /// Number of lines, counting the empty one after a final line ending.
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    /// The text of `line`, without its terminator.
+    pub fn line(&self, line: u32) -> Option<&'a str> {
+        let start = *self.line_starts.get(line as usize)?;
+        Some(line_at(self.content, start))
+    }
+}
+
+/// Applies a single content change, splicing its text into the given range or
+/// replacing the whole document when no range is given.
+pub fn apply_change(
+    content: &mut String,
+    change: TextDocumentContentChangeEvent,
+    encoding: Encoding,
+) {
+    match change.range {
+        Some(range) => {
+            let start = position_to_offset(content, range.start, encoding);
+            let end = position_to_offset(content, range.end, encoding).max(start);
+            content.replace_range(start..end, &change.text);
+        }
+        None => *content = change.text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        range: Option<((u32, u32), (u32, u32))>,
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range.map(|((sl, sc), (el, ec))| {
+                Range::new(Position::new(sl, sc), Position::new(el, ec))
+            }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_position_to_offset_utf16() {
+        let content = "a😀b\nc";
+        assert_eq!(
+            position_to_offset(content, Position::new(0, 1), Encoding::Utf16),
+            1
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(0, 3), Encoding::Utf16),
+            5
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(0, 99), Encoding::Utf16),
+            6
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(1, 1), Encoding::Utf16),
+            8
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(5, 0), Encoding::Utf16),
+            8
+        );
+    }
+
+    #[test]
+    fn test_utf8_positions() {
+        let content = "a😀b\nc";
+        assert_eq!(
+            position_to_offset(content, Position::new(0, 5), Encoding::Utf8),
+            5
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(0, 3), Encoding::Utf8),
+            5
+        );
+        let utf8 = LineIndex::new(content, Encoding::Utf8);
+        let utf16 = LineIndex::new(content, Encoding::Utf16);
+        assert_eq!(utf8.position(5), Position::new(0, 5));
+        assert_eq!(utf16.position(5), Position::new(0, 3));
+    }
+
+    #[test]
+    fn test_negotiate_encoding() {
+        use PositionEncodingKind as Kind;
+        assert_eq!(Encoding::negotiate(&[]), Encoding::Utf16);
+        assert_eq!(
+            Encoding::negotiate(&[Kind::UTF16, Kind::UTF8]),
+            Encoding::Utf8
+        );
+        assert_eq!(Encoding::negotiate(&[Kind::UTF32]), Encoding::Utf16);
+    }
+
+    #[test]
+    fn test_apply_single_line_edit() {
+        let mut content = "let foo = 1;\nlet bar = 2;".to_string();
+        apply_change(
+            &mut content,
+            change(Some(((0, 4), (0, 7))), "baz"),
+            Encoding::Utf16,
+        );
+        assert_eq!(content, "let baz = 1;\nlet bar = 2;");
+    }
+
+    #[test]
+    fn test_apply_multi_line_deletion() {
+        let mut content = "one\ntwo\nthree\nfour".to_string();
+        apply_change(
+            &mut content,
+            change(Some(((0, 3), (2, 5))), ""),
+            Encoding::Utf16,
+        );
+        assert_eq!(content, "one\nfour");
+    }
+
+    #[test]
+    fn test_apply_insert_at_end_of_file() {
+        let mut content = "one\ntwo".to_string();
+        apply_change(
+            &mut content,
+            change(Some(((1, 3), (1, 3))), "\nthree"),
+            Encoding::Utf16,
+        );
+        assert_eq!(content, "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn test_apply_change_batch_and_full_replacement() {
+        let mut content = "héllo wörld".to_string();
+        apply_change(
+            &mut content,
+            change(Some(((0, 6), (0, 11))), "there"),
+            Encoding::Utf16,
+        );
+        apply_change(
+            &mut content,
+            change(Some(((0, 0), (0, 5))), "hi"),
+            Encoding::Utf16,
+        );
+        assert_eq!(content, "hi there");
+        apply_change(&mut content, change(None, "replaced"), Encoding::Utf16);
+        assert_eq!(content, "replaced");
+    }
+
+    #[test]
+    fn test_line_index_position() {
+        let content = "ab\na😀b\n";
+        let index = LineIndex::new(content, Encoding::Utf16);
+        assert_eq!(index.position(0), Position::new(0, 0));
+        assert_eq!(index.position(2), Position::new(0, 2));
+        assert_eq!(index.position(3), Position::new(1, 0));
+        assert_eq!(index.position(8), Position::new(1, 3));
+        assert_eq!(index.position(content.len()), Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_crlf_and_lone_cr_line_endings() {
+        let content = "ab\r\ncd\ref\ngh";
+        assert_eq!(
+            position_to_offset(content, Position::new(1, 0), Encoding::Utf16),
+            4
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(1, 99), Encoding::Utf16),
+            6
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(2, 1), Encoding::Utf16),
+            8
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(3, 2), Encoding::Utf16),
+            12
+        );
+        assert_eq!(
+            position_to_offset(content, Position::new(0, 99), Encoding::Utf16),
+            2
+        );
+
+        let index = LineIndex::new(content, Encoding::Utf16);
+        assert_eq!(
+            index.range(4..6),
+            Range::new(Position::new(1, 0), Position::new(1, 2))
+        );
+        assert_eq!(index.position(7), Position::new(2, 0));
+        assert_eq!(index.position(10), Position::new(3, 0));
+        assert_eq!(index.line(0), Some("ab"));
+        assert_eq!(index.line(1), Some("cd"));
+        assert_eq!(index.line(3), Some("gh"));
+        assert_eq!(index.line(4), None);
+    }
+
+    #[test]
+    fn test_apply_change_in_crlf_document() {
+        let mut content = "one\r\ntwo\r\n".to_string();
+        apply_change(
+            &mut content,
+            change(Some(((1, 0), (1, 3))), "2"),
+            Encoding::Utf16,
+        );
+        assert_eq!(content, "one\r\n2\r\n");
+    }
+}
@@ -0,0 +1,158 @@
+use regex::Regex;
+use std::num::NonZero;
+use std::ops::Range;
+use std::thread;
+
+/// Documents at least this large are tokenized on several threads.
+const PARALLEL_THRESHOLD: usize = 256 * 1024;
+
+/// Byte ranges of every match of `pattern` in `content`, exactly as `Regex::find_iter`
+/// would report them. Large documents are split into chunks that are scanned in parallel.
+pub fn find_words(pattern: &Regex, content: &str) -> Vec<Range<usize>> {
+    let chunks = if content.len() < PARALLEL_THRESHOLD {
+        1
+    } else {
+        thread::available_parallelism().map_or(1, NonZero::get)
+    };
+    find_words_in_chunks(pattern, content, chunks)
+}
+
+fn find_words_in_chunks(pattern: &Regex, content: &str, chunks: usize) -> Vec<Range<usize>> {
+    let starts = chunk_starts(content, chunks);
+    let bounds = starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&content.len()]))
+        .map(|(&start, &end)| start..end)
+        .collect::<Vec<_>>();
+    let parts = if bounds.len() == 1 {
+        vec![scan(pattern, content, 0, content.len())]
+    } else {
+        thread::scope(|scope| {
+            let handles = bounds
+                .iter()
+                .map(|bounds| scope.spawn(|| scan(pattern, content, bounds.start, bounds.end)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Tokenizer thread panicked"))
+                .collect()
+        })
+    };
+
+    let mut words: Vec<Range<usize>> = Vec::new();
+    for (part, bounds) in parts.into_iter().zip(bounds) {
+        let mut pos = words.last().map_or(0, |word| word.end);
+        if pos <= bounds.start {
+            words.extend(part);
+            continue;
+        }
+        // The previous chunk's last word runs into this one, so this chunk's first matches may
+        // start mid-word. Rescan from where that word ended until both scans agree again.
+        let mut rest = part.into_iter().peekable();
+        while let Some(m) = pattern.find_at(content, pos) {
+            if m.start() >= bounds.end {
+                break;
+            }
+            while rest.next_if(|word| word.start < m.start()).is_some() {}
+            if rest.peek() == Some(&m.range()) {
+                words.extend(rest);
+                break;
+            }
+            words.push(m.range());
+            pos = next_search(content, m.range());
+        }
+    }
+    words
+}
+
+/// Start offsets of up to `chunks` pieces of `content`, each beginning at a line start.
+fn chunk_starts(content: &str, chunks: usize) -> Vec<usize> {
+    let bytes = content.as_bytes();
+    let mut starts = vec![0];
+    for i in 1..chunks {
+        let target = content.len() * i / chunks;
+        let Some(newline) = bytes[target..].iter().position(|&b| b == b'\n') else {
+            break;
+        };
+        let start = target + newline + 1;
+        if start < content.len() && starts.last() < Some(&start) {
+            starts.push(start);
+        }
+    }
+    starts
+}
+
+/// Matches starting in `start..end`, scanning from `start`. The last one may extend past `end`.
+fn scan(pattern: &Regex, content: &str, start: usize, end: usize) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut pos = start;
+    while pos <= content.len() {
+        let Some(m) = pattern.find_at(content, pos) else {
+            break;
+        };
+        if m.start() >= end {
+            break;
+        }
+        words.push(m.range());
+        pos = next_search(content, m.range());
+    }
+    words
+}
+
+/// Where to search after a match; an empty match moves on by one character.
+fn next_search(content: &str, word: Range<usize>) -> usize {
+    if word.is_empty() {
+        word.end + content[word.end..].chars().next().map_or(1, char::len_utf8)
+    } else {
+        word.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequential(pattern: &Regex, content: &str) -> Vec<Range<usize>> {
+        pattern.find_iter(content).map(|m| m.range()).collect()
+    }
+
+    fn synthetic_document() -> String {
+        (0..20_000)
+            .map(|i| format!("line{i} alpha_{} βeta{} x{}\n", i % 97, i % 13, i % 7))
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let content = synthetic_document();
+        assert!(content.len() >= PARALLEL_THRESHOLD);
+        let pattern = Regex::new(crate::config::DEFAULT_WORD_PATTERN).unwrap();
+        let expected = sequential(&pattern, &content);
+        for chunks in [1, 2, 7, 16] {
+            assert_eq!(find_words_in_chunks(&pattern, &content, chunks), expected);
+        }
+        assert_eq!(find_words(&pattern, &content), expected);
+    }
+
+    #[test]
+    fn test_words_spanning_chunk_boundaries() {
+        // Words may contain newlines under this pattern, so they cross every chunk boundary.
+        let content = "ab\ncd\nef gh\nij\n".repeat(500);
+        let pattern = Regex::new(r"[a-z\n]+").unwrap();
+        let expected = sequential(&pattern, &content);
+        for chunks in [2, 3, 8] {
+            assert_eq!(find_words_in_chunks(&pattern, &content, chunks), expected);
+        }
+    }
+
+    #[test]
+    fn test_chunk_starts_are_line_starts() {
+        let content = "one\ntwo\nthree\nfour\n";
+        let starts = chunk_starts(content, 3);
+        assert_eq!(starts[0], 0);
+        for &start in &starts[1..] {
+            assert_eq!(&content[start - 1..start], "\n");
+        }
+        assert_eq!(chunk_starts("no newline", 4), [0]);
+    }
+}
@@ -0,0 +1,685 @@
+use crate::config::Config;
+use crate::gitignore::IgnoreRules;
+use crate::index::WordIndex;
+use crate::intern::{self, intern};
+use crate::load_all_words;
+use crate::progress::Progress;
+use lsp_types::Uri;
+use std::collections::HashMap;
+use std::fs;
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tracing::debug;
+
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    if !uri.scheme()?.as_str().eq_ignore_ascii_case("file") {
+        return None;
+    }
+    let path = uri.path().as_estr().decode().into_string().ok()?;
+    Some(PathBuf::from(path.as_ref()))
+}
+
+/// Words of the files under the workspace folders, so that files which are not
+/// open in the editor still contribute completions.
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    roots: Vec<PathBuf>,
+    files: HashMap<PathBuf, IndexedFile>,
+    words: WordIndex,
+    /// Bumped by every rescan so that a background scan still running for older
+    /// settings cannot add its files afterwards.
+    generation: u64,
+    /// Frequencies saved by the previous session, used for words not seen in this one yet.
+    cached: WordIndex,
+}
+
+/// The words of one file, with the workspace folder it was found under.
+#[derive(Debug)]
+struct IndexedFile {
+    root: Arc<Path>,
+    words: HashMap<Arc<str>, usize>,
+}
+
+impl IndexedFile {
+    /// `words` with interned keys, shared with the workspace [`WordIndex`] and open documents.
+    fn new(root: Arc<Path>, words: HashMap<String, usize>) -> IndexedFile {
+        let words = words
+            .into_iter()
+            .map(|(word, count)| (intern(&word), count))
+            .collect();
+        IndexedFile { root, words }
+    }
+}
+
+impl WorkspaceIndex {
+    /// Indexes every file under `roots` before returning.
+    #[cfg(test)]
+    pub fn scan(roots: &[PathBuf], config: &Config) -> Self {
+        let mut index = WorkspaceIndex {
+            roots: roots.to_vec(),
+            ..Default::default()
+        };
+        index.rescan(config);
+        index
+    }
+
+    /// Indexes every file under `roots` on worker threads, returning at once. Until the
+    /// returned handle finishes, the index holds whatever has been read so far.
+    /// `progress`, if given, is advanced as files are read and ended with the scan.
+    pub fn scan_in_background(
+        roots: &[PathBuf],
+        config: &Config,
+        progress: Option<Progress>,
+    ) -> (Arc<Mutex<WorkspaceIndex>>, JoinHandle<()>) {
+        let index = Arc::new(Mutex::new(WorkspaceIndex {
+            roots: roots.to_vec(),
+            ..Default::default()
+        }));
+        let handle = WorkspaceIndex::rescan_in_background(&index, config, progress);
+        (index, handle)
+    }
+
+    /// Like [`WorkspaceIndex::rescan`], but reading on worker threads and returning at once:
+    /// the files read under the old settings are dropped, and the index fills up again as
+    /// the returned handle runs. A scan still running for older settings stops adding files
+    /// and ends its progress.
+    pub fn rescan_in_background(
+        shared: &Arc<Mutex<WorkspaceIndex>>,
+        config: &Config,
+        progress: Option<Progress>,
+    ) -> JoinHandle<()> {
+        let (roots, generation) = {
+            let mut index = shared.lock().unwrap();
+            index.generation += 1;
+            index.files.clear();
+            index.words = WordIndex::default();
+            (index.roots.clone(), index.generation)
+        };
+        let shared = Arc::clone(shared);
+        let config = config.clone();
+        thread::spawn(move || {
+            let paths = collect_files(&roots, config.respect_gitignore);
+            let total = paths.len();
+            let done = AtomicUsize::new(0);
+            read_files(paths, &config, |path, root, words| {
+                let mut index = shared.lock().unwrap();
+                if index.generation != generation {
+                    if let Some(progress) = &progress {
+                        progress.clone().end();
+                    }
+                    return;
+                }
+                // Folders removed meanwhile must not get their files back.
+                if let Some(words) = words
+                    && index.covers(&path)
+                {
+                    index.insert_file(path, root, words);
+                }
+                drop(index);
+                if let Some(progress) = &progress {
+                    progress.report(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+                }
+            });
+            if let Some(progress) = progress {
+                progress.end();
+            }
+        })
+    }
+
+    /// Re-reads every file under the workspace roots, e.g. after the word settings changed.
+    pub fn rescan(&mut self, config: &Config) {
+        self.generation += 1;
+        let files = Mutex::new(HashMap::new());
+        read_files(
+            collect_files(&self.roots, config.respect_gitignore),
+            config,
+            |path, root, words| {
+                if let Some(words) = words {
+                    files
+                        .lock()
+                        .unwrap()
+                        .insert(path, IndexedFile::new(root, words));
+                }
+            },
+        );
+        self.files = files.into_inner().unwrap();
+        self.rebuild();
+    }
+
+    /// Follows a change of the workspace folders: files under `added` are read, and files no
+    /// longer under any folder are dropped along with the words only they contained.
+    pub fn change_folders(&mut self, added: &[PathBuf], removed: &[PathBuf], config: &Config) {
+        self.roots.retain(|root| !removed.contains(root));
+        let roots = &self.roots;
+        self.files
+            .retain(|path, _| roots.iter().any(|root| path.starts_with(root)));
+        let new_paths = collect_files(added, config.respect_gitignore)
+            .into_iter()
+            .filter(|(path, _)| !self.covers(path))
+            .collect();
+        self.roots.extend(added.iter().cloned());
+        let files = Mutex::new(HashMap::new());
+        read_files(new_paths, config, |path, root, words| {
+            if let Some(words) = words {
+                files
+                    .lock()
+                    .unwrap()
+                    .insert(path, IndexedFile::new(root, words));
+            }
+        });
+        self.files.extend(files.into_inner().unwrap());
+        self.rebuild();
+    }
+
+    /// Re-reads a file that changed on disk. Files that are gone, unreadable, outside the
+    /// workspace folders or skipped by the scan end up without words.
+    pub fn update_file(&mut self, path: &Path, config: &Config) {
+        self.remove_file(path);
+        let Some(root) = self.scanned_root(path, config.respect_gitignore) else {
+            return;
+        };
+        if let Some(text) = read_text_file(path, config.max_file_size_bytes) {
+            let words = load_all_words(&text, config);
+            self.insert_file(path.to_path_buf(), Arc::from(root), words);
+        }
+    }
+
+    fn remove_file(&mut self, path: &Path) {
+        if let Some(file) = self.files.remove(path) {
+            for (word, count) in &file.words {
+                self.words.remove(word, *count);
+            }
+        }
+    }
+
+    /// The workspace folder a scan would read `path` under, if it would: `path` is inside
+    /// it, with no dot-entry on the way and, if `respect_ignore_files`, not excluded by an
+    /// ignore file.
+    fn scanned_root(&self, path: &Path, respect_ignore_files: bool) -> Option<PathBuf> {
+        let root = distinct_roots(&self.roots)
+            .into_iter()
+            .find(|root| path.starts_with(root))?;
+        let components = path
+            .strip_prefix(&root)
+            .unwrap_or(path)
+            .components()
+            .collect::<Vec<_>>();
+        let mut rules = respect_ignore_files.then(|| IgnoreRules::above(&root).with_dir(&root));
+        let mut current = root.clone();
+        for (i, component) in components.iter().enumerate() {
+            if component.as_os_str().to_string_lossy().starts_with('.') {
+                return None;
+            }
+            current.push(component);
+            let is_dir = i + 1 < components.len();
+            if let Some(ignore) = &rules {
+                if ignore.is_ignored(&current, is_dir) {
+                    return None;
+                }
+                if is_dir {
+                    rules = Some(ignore.with_dir(&current));
+                }
+            }
+        }
+        (!components.is_empty()).then_some(root)
+    }
+
+    /// Whether `path` is inside one of the workspace folders.
+    fn covers(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Words of every indexed file, with their counts summed.
+    pub fn words(&self) -> &WordIndex {
+        &self.words
+    }
+
+    /// Every indexed file with its word counts.
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &HashMap<Arc<str>, usize>)> {
+        self.files
+            .iter()
+            .map(|(path, file)| (path.as_path(), &file.words))
+    }
+
+    /// The workspace folder `path` was indexed from, if it is indexed.
+    pub fn file_root(&self, path: &Path) -> Option<&Path> {
+        self.files.get(path).map(|file| &*file.root)
+    }
+
+    /// Number of files whose words are indexed.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn cached_words(&self) -> &WordIndex {
+        &self.cached
+    }
+
+    pub fn set_cached_words(&mut self, cached: WordIndex) {
+        self.cached = cached;
+    }
+
+    fn insert_file(&mut self, path: PathBuf, root: Arc<Path>, words: HashMap<String, usize>) {
+        let file = IndexedFile::new(root, words);
+        for (word, count) in &file.words {
+            self.words.add(word, *count);
+        }
+        self.files.insert(path, file);
+    }
+
+    fn rebuild(&mut self) {
+        let mut words = WordIndex::default();
+        for file in self.files.values() {
+            for (word, count) in &file.words {
+                words.add(word, *count);
+            }
+        }
+        self.words = words;
+        intern::sweep();
+    }
+}
+
+/// `roots` without duplicates and without folders inside other roots, so that no file is
+/// indexed twice.
+fn distinct_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut distinct: Vec<PathBuf> = Vec::new();
+    for root in roots {
+        if distinct.iter().any(|other| root.starts_with(other)) {
+            continue;
+        }
+        distinct.retain(|other| !other.starts_with(root));
+        distinct.push(root.clone());
+    }
+    distinct
+}
+
+/// Every regular file under `roots`, with the root it was found under, skipping dot-entries
+/// and, if `respect_ignore_files`, paths excluded by `.gitignore` or `.ignore` files, including
+/// those above the root in its git repository, or by `.git/info/exclude`.
+fn collect_files(roots: &[PathBuf], respect_ignore_files: bool) -> Vec<(PathBuf, Arc<Path>)> {
+    let mut files = Vec::new();
+    for root in &distinct_roots(roots) {
+        let rules = respect_ignore_files.then(|| IgnoreRules::above(root));
+        let mut paths = Vec::new();
+        collect_dir(root, rules.as_ref(), &mut paths);
+        let root = Arc::<Path>::from(root.as_path());
+        files.extend(paths.into_iter().map(|path| (path, Arc::clone(&root))));
+    }
+    files
+}
+
+fn collect_dir(dir: &Path, rules: Option<&IgnoreRules>, paths: &mut Vec<PathBuf>) {
+    let rules = rules.map(|rules| rules.with_dir(dir));
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Skipping directory {}: {e}", dir.display());
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        // `DirEntry::file_type` does not follow symlinks, which keeps link cycles out of the walk.
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if rules
+            .as_ref()
+            .is_some_and(|rules| rules.is_ignored(&path, file_type.is_dir()))
+        {
+            continue;
+        }
+        if file_type.is_dir() {
+            collect_dir(&path, rules.as_ref(), paths);
+        } else if file_type.is_file() {
+            paths.push(path);
+        }
+    }
+}
+
+/// Reads and tokenizes `paths` on one worker thread per available core, handing the
+/// words of each file to `merge` with its root as soon as it is done; `None` for skipped
+/// files.
+fn read_files(
+    paths: Vec<(PathBuf, Arc<Path>)>,
+    config: &Config,
+    merge: impl Fn(PathBuf, Arc<Path>, Option<HashMap<String, usize>>) + Sync,
+) {
+    let workers = thread::available_parallelism().map_or(1, NonZero::get);
+    let queue = Mutex::new(paths);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some((path, root)) = queue.lock().unwrap().pop() {
+                    let words = read_text_file(&path, config.max_file_size_bytes)
+                        .map(|text| load_all_words(&text, config));
+                    merge(path, root, words);
+                }
+            });
+        }
+    });
+}
+
+/// The language identifier clients commonly send for `path`, judging by its extension; empty
+/// when it is not one of those.
+pub fn language_id(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("rs") => "rust",
+        Some("py") => "python",
+        Some("js" | "mjs" | "cjs") => "javascript",
+        Some("jsx") => "javascriptreact",
+        Some("ts" | "mts" | "cts") => "typescript",
+        Some("tsx") => "typescriptreact",
+        Some("go") => "go",
+        Some("c" | "h") => "c",
+        Some("cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx") => "cpp",
+        Some("java") => "java",
+        Some("rb") => "ruby",
+        Some("pl" | "pm") => "perl",
+        Some("r") => "r",
+        Some("sh" | "bash" | "zsh") => "shellscript",
+        Some("yaml" | "yml") => "yaml",
+        Some("toml") => "toml",
+        Some("md" | "markdown") => "markdown",
+        Some("txt") => "plaintext",
+        _ => "",
+    }
+}
+
+/// The text of `path`, unless it is larger than `max_size` bytes or not UTF-8.
+pub fn read_text_file(path: &Path, max_size: u64) -> Option<String> {
+    // Checked before reading so that huge files never have to be loaded.
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.len() > max_size {
+        debug!("Skipping large file {}", path.display());
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    String::from_utf8(bytes)
+        .inspect_err(|_| debug!("Skipping non-UTF-8 file {}", path.display()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::Message;
+    use lsp_types::{ProgressParams, ProgressParamsValue, WorkDoneProgress};
+
+    fn temp_workspace(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("lsp-word-test-{name}"));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_uri_to_path() {
+        let uri = "file:///tmp/some%20dir/file.rs".parse::<Uri>().unwrap();
+        assert_eq!(
+            uri_to_path(&uri),
+            Some(PathBuf::from("/tmp/some dir/file.rs"))
+        );
+        let uri = "untitled:Untitled-1".parse::<Uri>().unwrap();
+        assert_eq!(uri_to_path(&uri), None);
+    }
+
+    #[test]
+    fn test_scan_indexes_nested_files() {
+        let root = temp_workspace(
+            "scan",
+            &[
+                ("main.rs", b"fn alpha() {}"),
+                ("src/lib.rs", b"fn beta() { alpha() }"),
+                (".git/config", b"hidden"),
+                ("binary.bin", &[0xff, 0xfe, 0x00]),
+            ],
+        );
+        let index = WorkspaceIndex::scan(&[root], &Config::default());
+        assert_eq!(
+            index.words().prefix_completions(""),
+            ["alpha", "beta", "fn"]
+        );
+        assert_eq!(index.words().count("alpha"), 2);
+    }
+
+    #[test]
+    fn test_scan_indexes_every_root() {
+        let first = temp_workspace("root-first", &[("a.txt", b"alpha shared")]);
+        let second = temp_workspace("root-second", &[("nested/b.txt", b"beta shared")]);
+        let nested = second.join("nested");
+        let index = WorkspaceIndex::scan(
+            &[first.clone(), second.clone(), nested.clone(), first.clone()],
+            &Config::default(),
+        );
+        assert_eq!(
+            index.words().prefix_completions(""),
+            ["alpha", "beta", "shared"]
+        );
+        // Overlapping roots still count each file once.
+        assert_eq!(index.words().count("beta"), 1);
+        assert_eq!(index.words().count("shared"), 2);
+        // Each file remembers the folder it was found under.
+        assert_eq!(index.file_root(&first.join("a.txt")), Some(first.as_path()));
+        assert_eq!(
+            index.file_root(&nested.join("b.txt")),
+            Some(second.as_path())
+        );
+    }
+
+    #[test]
+    fn test_change_folders() {
+        let first = temp_workspace("folders-first", &[("a.txt", b"alpha shared")]);
+        let second = temp_workspace("folders-second", &[("b.txt", b"beta shared")]);
+        let config = Config::default();
+        let mut index = WorkspaceIndex::scan(std::slice::from_ref(&first), &config);
+        assert_eq!(index.words().prefix_completions(""), ["alpha", "shared"]);
+
+        index.change_folders(std::slice::from_ref(&second), &[], &config);
+        assert_eq!(
+            index.words().prefix_completions(""),
+            ["alpha", "beta", "shared"]
+        );
+        assert_eq!(index.words().count("shared"), 2);
+
+        index.change_folders(&[], &[second], &config);
+        assert_eq!(index.words().prefix_completions(""), ["alpha", "shared"]);
+        assert_eq!(index.words().count("shared"), 1);
+    }
+
+    #[test]
+    fn test_update_file() {
+        let root = temp_workspace(
+            "update-file",
+            &[
+                ("a.txt", b"before"),
+                (".gitignore", b"ignored.txt"),
+                ("ignored.txt", b"hidden"),
+            ],
+        );
+        let config = Config::default();
+        let mut index = WorkspaceIndex::scan(std::slice::from_ref(&root), &config);
+        assert_eq!(index.words().prefix_completions(""), ["before"]);
+
+        fs::write(root.join("a.txt"), "after").unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/b.txt"), "created").unwrap();
+        fs::write(root.join("ignored.txt"), "still hidden").unwrap();
+        fs::write(root.join(".hidden"), "dotted").unwrap();
+        for name in ["a.txt", "sub/b.txt", "ignored.txt", ".hidden"] {
+            index.update_file(&root.join(name), &config);
+        }
+        index.update_file(Path::new("/elsewhere/file.txt"), &config);
+        assert_eq!(index.words().prefix_completions(""), ["after", "created"]);
+        assert_eq!(
+            index.file_root(&root.join("sub/b.txt")),
+            Some(root.as_path())
+        );
+
+        fs::remove_file(root.join("a.txt")).unwrap();
+        index.update_file(&root.join("a.txt"), &config);
+        assert_eq!(index.words().prefix_completions(""), ["created"]);
+    }
+
+    #[test]
+    fn test_language_id() {
+        assert_eq!(language_id(Path::new("/src/main.rs")), "rust");
+        assert_eq!(language_id(Path::new("App.TSX")), "typescriptreact");
+        assert_eq!(language_id(Path::new("notes.txt")), "plaintext");
+        assert_eq!(language_id(Path::new("Makefile")), "");
+    }
+
+    #[test]
+    fn test_distinct_roots() {
+        let roots = ["/a/b", "/a", "/c", "/a", "/cd"].map(PathBuf::from);
+        assert_eq!(
+            distinct_roots(&roots),
+            ["/a", "/c", "/cd"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_scan_skips_invalid_utf8_files() {
+        let root = temp_workspace(
+            "invalid-utf8",
+            &[
+                ("latin1.txt", b"caf\xe9 latin"),
+                ("valid.txt", "caf\u{e9} valid".as_bytes()),
+            ],
+        );
+        let index = WorkspaceIndex::scan(&[root], &Config::default());
+        assert_eq!(index.words().prefix_completions(""), ["café", "valid"]);
+    }
+
+    #[test]
+    fn test_scan_respects_ignore_files() {
+        let files: &[(&str, &[u8])] = &[
+            (".gitignore", b"target/\n*.gen\n"),
+            ("src/main.rs", b"kept"),
+            ("src/.ignore", b"scratch.txt"),
+            ("src/scratch.txt", b"scratch"),
+            ("src/parser.gen", b"generated"),
+            ("target/debug/out.rs", b"built"),
+        ];
+        let root = temp_workspace("gitignore", files);
+        let index = WorkspaceIndex::scan(&[root], &Config::default());
+        assert_eq!(index.words().prefix_completions(""), ["kept"]);
+
+        let root = temp_workspace("gitignore-disabled", files);
+        let config = Config::from_value(Some(serde_json::json!({ "respectGitignore": false })));
+        let index = WorkspaceIndex::scan(&[root], &config);
+        assert_eq!(
+            index.words().prefix_completions(""),
+            ["built", "generated", "kept", "scratch"]
+        );
+    }
+
+    #[test]
+    fn test_scan_respects_ignore_files_above_root() {
+        let repo = temp_workspace(
+            "gitignore-above",
+            &[
+                (".git/info/exclude", b"*.tmp\n"),
+                (".gitignore", b"vendor/\n"),
+                ("app/main.rs", b"kept"),
+                ("app/notes.tmp", b"scratch"),
+                ("app/vendor/lib.rs", b"vendored"),
+            ],
+        );
+        let mut index = WorkspaceIndex::scan(&[repo.join("app")], &Config::default());
+        assert_eq!(index.words().prefix_completions(""), ["kept"]);
+
+        index.update_file(&repo.join("app/notes.tmp"), &Config::default());
+        assert_eq!(index.words().prefix_completions(""), ["kept"]);
+    }
+
+    #[test]
+    fn test_scan_skips_files_above_size_limit() {
+        let root = temp_workspace(
+            "size-limit",
+            &[
+                ("small.txt", b"small words"),
+                ("large.txt", b"oversized generated content"),
+            ],
+        );
+        let config = Config::from_value(Some(serde_json::json!({ "maxFileSizeBytes": 16 })));
+        let index = WorkspaceIndex::scan(&[root], &config);
+        assert_eq!(index.words().prefix_completions(""), ["small", "words"]);
+    }
+
+    #[test]
+    fn test_scan_in_background_indexes_every_file() {
+        let files: Vec<(String, Vec<u8>)> = (0..8)
+            .map(|i| {
+                (
+                    format!("dir{}/file{i}.txt", i % 3),
+                    format!("word{i} shared").into(),
+                )
+            })
+            .collect();
+        let files: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_slice()))
+            .collect();
+        let root = temp_workspace("background", &files);
+
+        let (index, handle) = WorkspaceIndex::scan_in_background(&[root], &Config::default(), None);
+        handle.join().unwrap();
+        let index = index.lock().unwrap();
+        let expected: Vec<String> = std::iter::once("shared".to_string())
+            .chain((0..8).map(|i| format!("word{i}")))
+            .collect();
+        assert_eq!(index.words().prefix_completions(""), expected);
+        assert_eq!(index.words().count("shared"), 8);
+    }
+
+    #[test]
+    fn test_scan_in_background_reports_progress() {
+        let root = temp_workspace("progress", &[("a.txt", b"alpha"), ("b.txt", b"beta")]);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let progress = Progress::begin(sender, "scan", "Indexing");
+        progress.created(&lsp_server::Response::new_ok("scan".to_string().into(), ()));
+
+        let (_, handle) =
+            WorkspaceIndex::scan_in_background(&[root], &Config::default(), Some(progress));
+        handle.join().unwrap();
+        let methods: Vec<String> = receiver
+            .try_iter()
+            .map(|message| match message {
+                Message::Request(req) => req.method,
+                Message::Notification(not) => {
+                    let params = serde_json::from_value::<ProgressParams>(not.params).unwrap();
+                    let ProgressParamsValue::WorkDone(value) = params.value;
+                    match value {
+                        WorkDoneProgress::Begin(_) => "begin",
+                        WorkDoneProgress::Report(_) => "report",
+                        WorkDoneProgress::End(_) => "end",
+                    }
+                    .to_string()
+                }
+                Message::Response(_) => panic!("Unexpected response"),
+            })
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                "window/workDoneProgress/create",
+                "begin",
+                "report",
+                "report",
+                "end"
+            ]
+        );
+    }
+}